@@ -0,0 +1,732 @@
+#![allow(warnings)]
+
+use candid::{ CandidType, Deserialize, Principal, Reserved };
+use ic_cdk::{ api::{ caller, time }, call };
+use ic_cdk_macros::*;
+use std::cell::RefCell;
+use std::collections::{ HashMap, HashSet };
+
+// ==================================================================================================
+// === Types & State ===
+// ==================================================================================================
+
+// Public Types
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CryptoState {
+    rekey_required: bool,
+    current_key_epoch: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct DirectMessage {
+    id: u64,
+    key_epoch_id: u32,
+    author_principal: Principal,
+    timestamp: u64,
+    encrypted_content_markdown: Vec<u8>,
+}
+
+// A conversation key wrapped with the public key of one of a participant's devices.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct WrappedKey {
+    key_epoch_id: u32,
+    device_id: u32,
+    encrypted_key: Vec<u8>,
+}
+
+// Device key (mirrors the user canister's `DeviceKey`; only the fields used here)
+#[derive(CandidType, Deserialize, Clone)]
+pub struct DeviceKey {
+    id: u32,
+}
+
+// `(participant, device_id, wrapped_key)` entries, one per registered device of every participant.
+type KeyBatch = Vec<(Principal, u32, Vec<u8>)>;
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ConversationSummary {
+    id: u64,
+    participants: Vec<Principal>,
+    is_group: bool,
+    created_at: u64,
+    last_message_at: Option<u64>,
+    unread_count: u64,
+    rekey_required: bool,
+    current_key_epoch: u32,
+}
+
+// One conversation as it appears in a user's data export: only the messages they wrote.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ExportedConversation {
+    id: u64,
+    participants: Vec<Principal>,
+    is_group: bool,
+    created_at: u64,
+    messages: Vec<DirectMessage>,
+}
+
+// Custom Types for State
+#[derive(CandidType, Deserialize, Clone)]
+struct Conversation {
+    id: u64,
+    participants: HashSet<Principal>,
+    is_group: bool,
+    created_by: Principal,
+    created_at: u64,
+    crypto_state: CryptoState,
+    wrapped_keys: HashMap<Principal, Vec<WrappedKey>>, // Current epoch only, one per device
+    messages: Vec<DirectMessage>, // Ordered by ID (oldest first)
+    next_message_id: u64,
+    read_cursors: HashMap<Principal, u64>, // Participant -> Last read message ID
+}
+
+// Custom Error Type
+#[derive(CandidType, Deserialize, Debug)]
+pub enum Error {
+    Unauthorized(String),
+    NotFound(String),
+    InvalidState(String),
+    ConfigError(String),
+    CallFailed(String),
+    ValidationError(String),
+}
+
+// State Definition
+type ConversationStore = HashMap<u64, Conversation>;
+type UserConversationIndex = HashMap<Principal, HashSet<u64>>;
+
+const MAX_GROUP_PARTICIPANTS: usize = 10;
+
+thread_local! {
+    static CONVERSATIONS: RefCell<ConversationStore> = RefCell::new(HashMap::new());
+    static USER_CONVERSATIONS: RefCell<UserConversationIndex> = RefCell::new(HashMap::new());
+    static NEXT_CONVERSATION_ID: RefCell<u64> = RefCell::new(0);
+    static OWNER: RefCell<Option<Principal>> = RefCell::new(None);
+
+    // Canister dependencies
+    static USER_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+}
+
+// Stable state for upgrades
+#[derive(CandidType, Deserialize)]
+struct StableState {
+    conversations: ConversationStore,
+    user_conversations: UserConversationIndex,
+    next_conversation_id: u64,
+    owner: Option<Principal>,
+    user_canister_id: Option<Principal>,
+}
+
+// ==================================================================================================
+// === Upgrade Hooks ===
+// ==================================================================================================
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    let state = StableState {
+        conversations: CONVERSATIONS.with(|s| s.borrow().clone()),
+        user_conversations: USER_CONVERSATIONS.with(|s| s.borrow().clone()),
+        next_conversation_id: NEXT_CONVERSATION_ID.with(|s| *s.borrow()),
+        owner: OWNER.with(|s| *s.borrow()),
+        user_canister_id: USER_CANISTER_ID.with(|s| *s.borrow()),
+    };
+    ic_cdk::storage::stable_save((state,)).unwrap();
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    let (state,): (StableState,) = ic_cdk::storage::stable_restore().unwrap();
+    CONVERSATIONS.with(|s| {
+        *s.borrow_mut() = state.conversations;
+    });
+    USER_CONVERSATIONS.with(|s| {
+        *s.borrow_mut() = state.user_conversations;
+    });
+    NEXT_CONVERSATION_ID.with(|s| {
+        *s.borrow_mut() = state.next_conversation_id;
+    });
+    OWNER.with(|s| {
+        *s.borrow_mut() = state.owner;
+    });
+    USER_CANISTER_ID.with(|s| {
+        *s.borrow_mut() = state.user_canister_id;
+    });
+}
+
+// ==================================================================================================
+// === Initialization ===
+// ==================================================================================================
+
+#[init]
+fn init(initial_owner: Principal, user_canister: Principal) {
+    OWNER.with(|o| {
+        *o.borrow_mut() = Some(initial_owner);
+    });
+    USER_CANISTER_ID.with(|id| {
+        *id.borrow_mut() = Some(user_canister);
+    });
+}
+
+// ==================================================================================================
+// === Helper Functions ===
+// ==================================================================================================
+
+fn user_canister_id() -> Result<Principal, Error> {
+    USER_CANISTER_ID.with(|id| *id.borrow()).ok_or_else(||
+        Error::ConfigError("User canister not configured.".to_string())
+    )
+}
+
+fn is_user_canister() -> Result<(), Error> {
+    if Some(caller()) == USER_CANISTER_ID.with(|id| *id.borrow()) {
+        Ok(())
+    } else {
+        Err(Error::Unauthorized("Caller is not the user canister.".to_string()))
+    }
+}
+
+/// Fails unless the caller is a participant of the conversation.
+fn ensure_participant(conversation: &Conversation) -> Result<(), Error> {
+    if conversation.participants.contains(&caller()) {
+        Ok(())
+    } else {
+        Err(Error::Unauthorized("Caller is not a participant of this conversation.".to_string()))
+    }
+}
+
+/// Same validation `sector_canister::rotate_sector_key` applies: the batch names exactly the
+/// participants, with no device listed twice. Device coverage is checked by `validate_devices`.
+fn validate_key_batch(
+    participants: &HashSet<Principal>,
+    key_batch: &KeyBatch
+) -> Result<(), Error> {
+    let batch_devices: HashSet<(Principal, u32)> = key_batch
+        .iter()
+        .map(|(p, device_id, _)| (*p, *device_id))
+        .collect();
+    if batch_devices.len() != key_batch.len() {
+        return Err(Error::ValidationError("Duplicate devices found in key batch.".to_string()));
+    }
+    let batch_principals: HashSet<Principal> = batch_devices
+        .iter()
+        .map(|(p, _)| *p)
+        .collect();
+    if &batch_principals != participants {
+        return Err(
+            Error::ValidationError(
+                "Key batch principals do not match the exact set of participants.".to_string()
+            )
+        );
+    }
+    Ok(())
+}
+
+/// Fails unless the batch wraps the key for every active device the participants have
+/// registered with the user canister. Devices the user canister no longer lists are allowed.
+async fn validate_devices(
+    participants: &HashSet<Principal>,
+    key_batch: &KeyBatch
+) -> Result<(), Error> {
+    let users: Vec<Principal> = participants.iter().cloned().collect();
+    let (devices,): (Vec<(Principal, Vec<DeviceKey>)>,) = call(
+        user_canister_id()?,
+        "get_device_keys_batch",
+        (users,)
+    ).await.map_err(|(code, msg)| {
+        Error::CallFailed(format!("Canister call failed ({:?}): {}", code, msg))
+    })?;
+    let registered: HashSet<(Principal, u32)> = devices
+        .into_iter()
+        .flat_map(|(user, keys)| keys.into_iter().map(move |key| (user, key.id)))
+        .collect();
+    let batch_devices: HashSet<(Principal, u32)> = key_batch
+        .iter()
+        .map(|(p, device_id, _)| (*p, *device_id))
+        .collect();
+    if !registered.is_subset(&batch_devices) {
+        return Err(
+            Error::ValidationError(
+                "Key batch must cover every registered device of the participants.".to_string()
+            )
+        );
+    }
+    Ok(())
+}
+
+fn store_wrapped_keys(conversation: &mut Conversation, key_batch: KeyBatch) {
+    let epoch = conversation.crypto_state.current_key_epoch;
+    let mut wrapped_keys: HashMap<Principal, Vec<WrappedKey>> = HashMap::new();
+    for (p, device_id, encrypted_key) in key_batch {
+        wrapped_keys
+            .entry(p)
+            .or_default()
+            .push(WrappedKey { key_epoch_id: epoch, device_id, encrypted_key });
+    }
+    conversation.wrapped_keys = wrapped_keys;
+}
+
+async fn profile_exists(user: Principal) -> Result<bool, Error> {
+    call::<_, (bool,)>(user_canister_id()?, "profile_exists", (user,)).await
+        .map(|(exists,)| exists)
+        .map_err(|(code, msg)|
+            Error::CallFailed(format!("Canister call failed ({:?}): {}", code, msg))
+        )
+}
+
+async fn has_blocked(user: Principal, other: Principal) -> Result<bool, Error> {
+    let (blocked,): (Result<bool, Reserved>,) = call(user_canister_id()?, "has_blocked", (
+        user,
+        other,
+    )).await.map_err(|(code, msg)| {
+        Error::CallFailed(format!("Canister call failed ({:?}): {}", code, msg))
+    })?;
+    blocked.map_err(|_| Error::ConfigError("User canister refused the block lookup.".to_string()))
+}
+
+fn summarize(conversation: &Conversation, viewer: Principal) -> ConversationSummary {
+    let last_read = conversation.read_cursors.get(&viewer).cloned();
+    let unread_count = conversation.messages
+        .iter()
+        .filter(|msg| msg.author_principal != viewer)
+        .filter(|msg| last_read.map_or(true, |cursor| msg.id > cursor))
+        .count() as u64;
+
+    ConversationSummary {
+        id: conversation.id,
+        participants: conversation.participants.iter().cloned().collect(),
+        is_group: conversation.is_group,
+        created_at: conversation.created_at,
+        last_message_at: conversation.messages.last().map(|msg| msg.timestamp),
+        unread_count,
+        rekey_required: conversation.crypto_state.rekey_required,
+        current_key_epoch: conversation.crypto_state.current_key_epoch,
+    }
+}
+
+// ==================================================================================================
+// === Public Query Calls ===
+// ==================================================================================================
+
+#[query]
+fn list_my_conversations() -> Vec<ConversationSummary> {
+    let caller = caller();
+    let ids = USER_CONVERSATIONS.with(|u| u.borrow().get(&caller).cloned().unwrap_or_default());
+
+    let mut summaries: Vec<ConversationSummary> = CONVERSATIONS.with(|c| {
+        let conversations = c.borrow();
+        ids.iter()
+            .filter_map(|id| conversations.get(id))
+            .map(|conversation| summarize(conversation, caller))
+            .collect()
+    });
+    // Most recently active first
+    summaries.sort_by(|a, b| {
+        b.last_message_at.unwrap_or(b.created_at).cmp(&a.last_message_at.unwrap_or(a.created_at))
+    });
+    summaries
+}
+
+#[query]
+fn get_direct_messages(
+    conversation_id: u64,
+    limit: usize,
+    before_id: Option<u64>
+) -> Result<Vec<DirectMessage>, Error> {
+    CONVERSATIONS.with(|c| {
+        let conversations = c.borrow();
+        let conversation = conversations
+            .get(&conversation_id)
+            .ok_or_else(|| Error::NotFound("Conversation not found.".to_string()))?;
+        ensure_participant(conversation)?;
+
+        let before_id = before_id.unwrap_or(u64::MAX);
+        Ok(
+            conversation.messages
+                .iter()
+                .rev() // Newest first
+                .filter(|msg| msg.id < before_id)
+                .take(limit)
+                .cloned()
+                .collect()
+        )
+    })
+}
+
+#[query]
+fn get_my_conversation_key(conversation_id: u64, device_id: u32) -> Result<WrappedKey, Error> {
+    CONVERSATIONS.with(|c| {
+        let conversations = c.borrow();
+        let conversation = conversations
+            .get(&conversation_id)
+            .ok_or_else(|| Error::NotFound("Conversation not found.".to_string()))?;
+        ensure_participant(conversation)?;
+
+        conversation.wrapped_keys
+            .get(&caller())
+            .and_then(|keys| keys.iter().find(|key| key.device_id == device_id))
+            .cloned()
+            .ok_or_else(|| {
+                Error::NotFound("No key has been wrapped for this device yet.".to_string())
+            })
+    })
+}
+
+#[query]
+fn get_unread_total() -> u64 {
+    list_my_conversations()
+        .iter()
+        .map(|summary| summary.unread_count)
+        .sum()
+}
+
+// ==================================================================================================
+// === Conversations ===
+// ==================================================================================================
+
+// Starts a 1:1 conversation (one other participant) or a small group. The creator wraps a fresh
+// conversation key for every registered device of every participant, themselves included.
+#[update]
+async fn create_conversation(
+    participants: Vec<Principal>,
+    key_batch: KeyBatch
+) -> Result<u64, Error> {
+    let creator = caller();
+    if creator == Principal::anonymous() {
+        return Err(Error::Unauthorized("Anonymous principal not allowed.".to_string()));
+    }
+
+    let mut all_participants: HashSet<Principal> = participants.into_iter().collect();
+    all_participants.insert(creator);
+    if all_participants.len() < 2 {
+        return Err(Error::ValidationError("A conversation needs at least two people.".to_string()));
+    }
+    if all_participants.len() > MAX_GROUP_PARTICIPANTS {
+        return Err(
+            Error::ValidationError(
+                format!("Conversations are limited to {} participants.", MAX_GROUP_PARTICIPANTS)
+            )
+        );
+    }
+    validate_key_batch(&all_participants, &key_batch)?;
+    let is_group = all_participants.len() > 2;
+
+    // A 1:1 conversation between the same two people already exists: reuse it.
+    if !is_group {
+        let my_ids = USER_CONVERSATIONS.with(|u| u.borrow().get(&creator).cloned());
+        let existing = my_ids.and_then(|ids| {
+            CONVERSATIONS.with(|c| {
+                let conversations = c.borrow();
+                ids.into_iter().find(|id| {
+                    conversations.get(id).map_or(false, |conversation| {
+                        !conversation.is_group && conversation.participants == all_participants
+                    })
+                })
+            })
+        });
+        if let Some(id) = existing {
+            return Ok(id);
+        }
+    }
+
+    if !profile_exists(creator).await? {
+        return Err(Error::Unauthorized("You need a profile to start a conversation.".to_string()));
+    }
+    for participant in all_participants.iter().filter(|p| **p != creator) {
+        if !profile_exists(*participant).await? {
+            return Err(Error::NotFound(format!("User {} does not have a profile.", participant)));
+        }
+        if has_blocked(*participant, creator).await? {
+            return Err(Error::Unauthorized("You cannot message this user.".to_string()));
+        }
+    }
+    validate_devices(&all_participants, &key_batch).await?;
+
+    let id = NEXT_CONVERSATION_ID.with(|next_id| {
+        let mut next_id = next_id.borrow_mut();
+        let id = *next_id;
+        *next_id += 1;
+        id
+    });
+
+    let mut conversation = Conversation {
+        id,
+        participants: all_participants.clone(),
+        is_group,
+        created_by: creator,
+        created_at: time(),
+        crypto_state: CryptoState { rekey_required: false, current_key_epoch: 1 },
+        wrapped_keys: HashMap::new(),
+        messages: Vec::new(),
+        next_message_id: 0,
+        read_cursors: HashMap::new(),
+    };
+    store_wrapped_keys(&mut conversation, key_batch);
+
+    CONVERSATIONS.with(|c| c.borrow_mut().insert(id, conversation));
+    USER_CONVERSATIONS.with(|u| {
+        let mut index = u.borrow_mut();
+        for participant in all_participants {
+            index.entry(participant).or_default().insert(id);
+        }
+    });
+
+    Ok(id)
+}
+
+#[update]
+async fn send_direct_message(
+    conversation_id: u64,
+    encrypted_content: Vec<u8>,
+    key_epoch: u32
+) -> Result<u64, Error> {
+    let sender = caller();
+
+    let (participants, is_group) = CONVERSATIONS.with(|c| {
+        let conversations = c.borrow();
+        let conversation = conversations
+            .get(&conversation_id)
+            .ok_or_else(|| Error::NotFound("Conversation not found.".to_string()))?;
+        ensure_participant(conversation)?;
+        Ok::<_, Error>((conversation.participants.clone(), conversation.is_group))
+    })?;
+
+    // In a 1:1 conversation, blocking the sender stops new messages from arriving.
+    if !is_group {
+        if let Some(recipient) = participants.iter().find(|p| **p != sender) {
+            if has_blocked(*recipient, sender).await? {
+                return Err(Error::Unauthorized("You cannot message this user.".to_string()));
+            }
+        }
+    }
+
+    CONVERSATIONS.with(|c| {
+        let mut conversations = c.borrow_mut();
+        let conversation = conversations
+            .get_mut(&conversation_id)
+            .ok_or_else(|| Error::NotFound("Conversation not found.".to_string()))?;
+        ensure_participant(conversation)?;
+
+        if conversation.crypto_state.rekey_required {
+            return Err(
+                Error::InvalidState("The conversation key must be rotated first.".to_string())
+            );
+        }
+        if key_epoch != conversation.crypto_state.current_key_epoch {
+            return Err(Error::ValidationError("Message uses an outdated key epoch.".to_string()));
+        }
+
+        let id = conversation.next_message_id;
+        conversation.next_message_id += 1;
+        conversation.messages.push(DirectMessage {
+            id,
+            key_epoch_id: key_epoch,
+            author_principal: sender,
+            timestamp: time(),
+            encrypted_content_markdown: encrypted_content,
+        });
+        // Your own message counts as read
+        conversation.read_cursors.insert(sender, id);
+        Ok(id)
+    })
+}
+
+#[update]
+fn mark_conversation_read(conversation_id: u64, up_to_message_id: u64) -> Result<(), Error> {
+    CONVERSATIONS.with(|c| {
+        let mut conversations = c.borrow_mut();
+        let conversation = conversations
+            .get_mut(&conversation_id)
+            .ok_or_else(|| Error::NotFound("Conversation not found.".to_string()))?;
+        ensure_participant(conversation)?;
+
+        let cursor = conversation.read_cursors.entry(caller()).or_insert(up_to_message_id);
+        *cursor = (*cursor).max(up_to_message_id);
+        Ok(())
+    })
+}
+
+// Adds someone to a group conversation. The remaining participants must rekey before anyone
+// can send again, so the new member never receives the previous conversation key.
+#[update]
+async fn add_participant(conversation_id: u64, user: Principal) -> Result<(), Error> {
+    let caller = caller();
+    CONVERSATIONS.with(|c| {
+        let conversations = c.borrow();
+        let conversation = conversations
+            .get(&conversation_id)
+            .ok_or_else(|| Error::NotFound("Conversation not found.".to_string()))?;
+        ensure_participant(conversation)?;
+
+        if !conversation.is_group {
+            return Err(Error::InvalidState("Start a new group to add people.".to_string()));
+        }
+        if conversation.participants.contains(&user) {
+            return Err(Error::ValidationError("User is already a participant.".to_string()));
+        }
+        if conversation.participants.len() >= MAX_GROUP_PARTICIPANTS {
+            return Err(Error::InvalidState("Conversation is at its maximum size.".to_string()));
+        }
+        Ok(())
+    })?;
+
+    if !profile_exists(user).await? {
+        return Err(Error::NotFound("User does not have a profile.".to_string()));
+    }
+    if has_blocked(user, caller).await? {
+        return Err(Error::Unauthorized("You cannot add this user.".to_string()));
+    }
+
+    CONVERSATIONS.with(|c| {
+        let mut conversations = c.borrow_mut();
+        let conversation = conversations
+            .get_mut(&conversation_id)
+            .ok_or_else(|| Error::NotFound("Conversation not found.".to_string()))?;
+        if conversation.participants.len() >= MAX_GROUP_PARTICIPANTS {
+            return Err(Error::InvalidState("Conversation is at its maximum size.".to_string()));
+        }
+        conversation.participants.insert(user);
+        conversation.crypto_state.rekey_required = true;
+        Ok(())
+    })?;
+    USER_CONVERSATIONS.with(|u| u.borrow_mut().entry(user).or_default().insert(conversation_id));
+    Ok(())
+}
+
+#[update]
+fn leave_conversation(conversation_id: u64) -> Result<(), Error> {
+    let caller = caller();
+    CONVERSATIONS.with(|c| {
+        let mut conversations = c.borrow_mut();
+        let conversation = conversations
+            .get_mut(&conversation_id)
+            .ok_or_else(|| Error::NotFound("Conversation not found.".to_string()))?;
+        ensure_participant(conversation)?;
+
+        if !conversation.is_group {
+            return Err(Error::InvalidState("You cannot leave a 1:1 conversation.".to_string()));
+        }
+        conversation.participants.remove(&caller);
+        conversation.wrapped_keys.remove(&caller);
+        conversation.read_cursors.remove(&caller);
+        conversation.crypto_state.rekey_required = true;
+        Ok(())
+    })?;
+    USER_CONVERSATIONS.with(|u| {
+        if let Some(ids) = u.borrow_mut().get_mut(&caller) {
+            ids.remove(&conversation_id);
+        }
+    });
+    Ok(())
+}
+
+// Mirrors `sector_canister::rotate_sector_key`: any participant may supply a new batch of
+// wrapped keys covering every device of exactly the current participants, which advances the
+// key epoch.
+#[update]
+async fn rotate_conversation_key(conversation_id: u64, key_batch: KeyBatch) -> Result<(), Error> {
+    let participants = CONVERSATIONS.with(|c| {
+        let conversations = c.borrow();
+        let conversation = conversations
+            .get(&conversation_id)
+            .ok_or_else(|| Error::NotFound("Conversation not found.".to_string()))?;
+        ensure_participant(conversation)?;
+        validate_key_batch(&conversation.participants, &key_batch)?;
+        Ok::<_, Error>(conversation.participants.clone())
+    })?;
+    validate_devices(&participants, &key_batch).await?;
+
+    CONVERSATIONS.with(|c| {
+        let mut conversations = c.borrow_mut();
+        let conversation = conversations
+            .get_mut(&conversation_id)
+            .ok_or_else(|| Error::NotFound("Conversation not found.".to_string()))?;
+        // Someone may have joined or left while the device list was being fetched.
+        if conversation.participants != participants {
+            return Err(
+                Error::InvalidState(
+                    "Participants changed during key rotation; please retry.".to_string()
+                )
+            );
+        }
+
+        conversation.crypto_state.rekey_required = false;
+        conversation.crypto_state.current_key_epoch += 1;
+        store_wrapped_keys(conversation, key_batch);
+        Ok(())
+    })
+}
+
+// ==================================================================================================
+// === Account Lifecycle ===
+// ==================================================================================================
+
+// Every conversation `user` is part of, with the messages they wrote. Callable by the user
+// themselves or by the user canister when assembling a full data export.
+#[query]
+fn export_for(user: Principal) -> Result<Vec<ExportedConversation>, Error> {
+    if caller() != user {
+        is_user_canister()?;
+    }
+    let ids = USER_CONVERSATIONS.with(|u| u.borrow().get(&user).cloned().unwrap_or_default());
+    Ok(
+        CONVERSATIONS.with(|c| {
+            let conversations = c.borrow();
+            ids.iter()
+                .filter_map(|id| conversations.get(id))
+                .map(|conversation| ExportedConversation {
+                    id: conversation.id,
+                    participants: conversation.participants.iter().cloned().collect(),
+                    is_group: conversation.is_group,
+                    created_at: conversation.created_at,
+                    messages: conversation.messages
+                        .iter()
+                        .filter(|msg| msg.author_principal == user)
+                        .cloned()
+                        .collect(),
+                })
+                .collect()
+        })
+    )
+}
+
+// Called by the user canister when an account is deleted. The user leaves every conversation and
+// their messages are no longer attributed to them. A conversation left with a single participant
+// is removed; groups must rekey so the deleted account's devices lose access.
+#[update]
+fn forget_user(user: Principal) -> Result<(), Error> {
+    is_user_canister()?;
+    let ids = USER_CONVERSATIONS.with(|u| u.borrow_mut().remove(&user)).unwrap_or_default();
+    for id in ids {
+        let remaining = CONVERSATIONS.with(|c| {
+            let mut conversations = c.borrow_mut();
+            let conversation = conversations.get_mut(&id)?;
+            conversation.participants.remove(&user);
+            conversation.wrapped_keys.remove(&user);
+            conversation.read_cursors.remove(&user);
+            conversation.crypto_state.rekey_required = true;
+            for msg in conversation.messages.iter_mut() {
+                if msg.author_principal == user {
+                    msg.author_principal = Principal::anonymous();
+                }
+            }
+            Some(conversation.participants.clone())
+        });
+        if let Some(remaining) = remaining {
+            if remaining.len() < 2 {
+                CONVERSATIONS.with(|c| c.borrow_mut().remove(&id));
+                USER_CONVERSATIONS.with(|u| {
+                    let mut index = u.borrow_mut();
+                    for participant in remaining {
+                        if let Some(conversation_ids) = index.get_mut(&participant) {
+                            conversation_ids.remove(&id);
+                        }
+                    }
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+// Export the interface for the smart contract.
+ic_cdk::export_candid!();
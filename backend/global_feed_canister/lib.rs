@@ -1,10 +1,10 @@
 #![allow(warnings)]
 
-use candid::{ CandidType, Deserialize, Principal };
+use candid::{ CandidType, Deserialize, Principal, Reserved };
 use ic_cdk::api::time;
 use ic_cdk_macros::*;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{ HashMap, HashSet };
 
 // === Types & State ===
 
@@ -33,6 +33,7 @@ pub struct GlobalPost {
     content_markdown: String,
     timestamp: u64, // Represented as nanoseconds from epoch
     origin_sector_id: Option<Principal>,
+    content_hash: Option<Vec<u8>>, // Author's SHA-256 of the content, verified by the origin sector
 }
 
 #[derive(CandidType, Deserialize)]
@@ -43,9 +44,30 @@ pub struct DirectPostSubmission {
 #[derive(CandidType, Deserialize)]
 pub struct SectorPostSubmission {
     author_principal: Principal,
-    author_username: String,
     content_markdown: String,
-    origin_sector_id: Principal,
+    content_hash: Option<Vec<u8>>,
+}
+
+// Actor Interfaces for Inter-Canister Calls
+// Note: Only the fields needed to attribute a post are decoded from the user canister's profile.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct UserProfile {
+    username: String,
+    tags: Vec<UserTag>,
+}
+
+// Mirrors the notification canister's interface (only the kinds this canister raises).
+#[derive(CandidType, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    GlobalPostPublished,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct NotificationPayload {
+    kind: NotificationKind,
+    actor: Option<Principal>,
+    reference: Option<String>,
+    message: String,
 }
 
 type PostStore = HashMap<u64, GlobalPost>;
@@ -55,99 +77,119 @@ type PrincipalSet = HashMap<Principal, ()>;
 thread_local! {
     static POSTS: RefCell<PostStore> = RefCell::new(HashMap::new());
     static VETTED_SECTORS: RefCell<PrincipalSet> = RefCell::new(HashMap::new());
-    static GLOBAL_POSTERS: RefCell<PrincipalSet> = RefCell::new(HashMap::new());
-    static ADMINS: RefCell<PrincipalSet> = RefCell::new(HashMap::new());
     static NEXT_POST_ID: RefCell<u64> = RefCell::new(0);
     static OWNER: RefCell<Principal> = RefCell::new(Principal::from_text("2vxsx-fae").unwrap());
     static GOVERNANCE_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    static USER_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    static NOTIFICATION_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    // Roles from the lists this canister kept before the user canister took them over, waiting
+    // to be handed over by `apply_legacy_grants`.
+    static PENDING_LEGACY_GRANTS: RefCell<Vec<(Principal, UserTag)>> = RefCell::new(Vec::new());
 }
 
 // === Upgrade Hooks ===
 
+#[derive(CandidType, Deserialize)]
+struct StableState {
+    posts: Vec<(u64, GlobalPost)>,
+    next_post_id: u64,
+    vetted_sectors: Vec<(Principal, ())>,
+    owner: Principal,
+    governance_canister_id: Option<Principal>,
+    user_canister_id: Option<Principal>,
+    notification_canister_id: Option<Principal>,
+    pending_legacy_grants: Option<Vec<(Principal, UserTag)>>,
+}
+
+// The positional tuple saved by releases that still kept their own admin and global poster
+// lists. Those lists now live in the user canister, so they are kept as pending grants.
+type LegacyStableState = (
+    Vec<(u64, GlobalPost)>,
+    u64,
+    Vec<(Principal, ())>,
+    Vec<(Principal, ())>, // Global posters
+    Vec<(Principal, ())>, // Admins
+    Principal,
+    Option<Principal>,
+);
+
 #[pre_upgrade]
 fn pre_upgrade() {
-    let posts_entries: Vec<(u64, GlobalPost)> = POSTS.with(|p|
-        p
-            .borrow()
-            .iter()
-            .map(|(k, v)| (*k, v.clone()))
-            .collect()
-    );
-    let vetted_sectors_entries: Vec<(Principal, ())> = VETTED_SECTORS.with(|s|
-        s
-            .borrow()
-            .iter()
-            .map(|(k, v)| (*k, *v))
-            .collect()
-    );
-    let global_posters_entries: Vec<(Principal, ())> = GLOBAL_POSTERS.with(|p|
-        p
-            .borrow()
-            .iter()
-            .map(|(k, v)| (*k, *v))
-            .collect()
-    );
-    let admins_entries: Vec<(Principal, ())> = ADMINS.with(|a|
-        a
-            .borrow()
-            .iter()
-            .map(|(k, v)| (*k, *v))
-            .collect()
-    );
-
-    let state = (
-        posts_entries,
-        NEXT_POST_ID.with(|id| *id.borrow()),
-        vetted_sectors_entries,
-        global_posters_entries,
-        admins_entries,
-        OWNER.with(|o| *o.borrow()),
-        GOVERNANCE_CANISTER_ID.with(|id| *id.borrow()),
-    );
+    let state = StableState {
+        posts: POSTS.with(|p|
+            p
+                .borrow()
+                .iter()
+                .map(|(k, v)| (*k, v.clone()))
+                .collect()
+        ),
+        next_post_id: NEXT_POST_ID.with(|id| *id.borrow()),
+        vetted_sectors: VETTED_SECTORS.with(|s|
+            s
+                .borrow()
+                .iter()
+                .map(|(k, v)| (*k, *v))
+                .collect()
+        ),
+        owner: OWNER.with(|o| *o.borrow()),
+        governance_canister_id: GOVERNANCE_CANISTER_ID.with(|id| *id.borrow()),
+        user_canister_id: USER_CANISTER_ID.with(|id| *id.borrow()),
+        notification_canister_id: NOTIFICATION_CANISTER_ID.with(|id| *id.borrow()),
+        pending_legacy_grants: Some(PENDING_LEGACY_GRANTS.with(|g| g.borrow().clone())),
+    };
 
     ic_cdk::storage::stable_save((state,)).unwrap();
 }
 
 #[post_upgrade]
 fn post_upgrade() {
-    let (
-        posts_entries,
-        next_post_id,
-        vetted_sectors_entries,
-        global_posters_entries,
-        admins_entries,
-        owner,
-        governance_canister_id,
-    ): (
-        Vec<(u64, GlobalPost)>,
-        u64,
-        Vec<(Principal, ())>,
-        Vec<(Principal, ())>,
-        Vec<(Principal, ())>,
-        Principal,
-        Option<Principal>,
-    ) = ic_cdk::storage::stable_restore().unwrap();
+    let state = match ic_cdk::storage::stable_restore::<(StableState,)>() {
+        Ok((state,)) => state,
+        Err(_) => {
+            let (
+                (posts, next_post_id, vetted_sectors, global_posters, admins, owner, governance),
+            ): (LegacyStableState,) = ic_cdk::storage::stable_restore().unwrap();
+            let pending_legacy_grants = global_posters
+                .into_iter()
+                .map(|(principal, ())| (principal, UserTag::GlobalPoster))
+                .chain(admins.into_iter().map(|(principal, ())| (principal, UserTag::Admin)))
+                .collect();
+            StableState {
+                posts,
+                next_post_id,
+                vetted_sectors,
+                owner,
+                governance_canister_id: governance,
+                user_canister_id: None,
+                notification_canister_id: None,
+                pending_legacy_grants: Some(pending_legacy_grants),
+            }
+        }
+    };
 
     POSTS.with(|p| {
-        *p.borrow_mut() = posts_entries.into_iter().collect();
+        *p.borrow_mut() = state.posts.into_iter().collect();
     });
     NEXT_POST_ID.with(|id| {
-        *id.borrow_mut() = next_post_id;
+        *id.borrow_mut() = state.next_post_id;
     });
     VETTED_SECTORS.with(|s| {
-        *s.borrow_mut() = vetted_sectors_entries.into_iter().collect();
-    });
-    GLOBAL_POSTERS.with(|p| {
-        *p.borrow_mut() = global_posters_entries.into_iter().collect();
-    });
-    ADMINS.with(|a| {
-        *a.borrow_mut() = admins_entries.into_iter().collect();
+        *s.borrow_mut() = state.vetted_sectors.into_iter().collect();
     });
     OWNER.with(|o| {
-        *o.borrow_mut() = owner;
+        *o.borrow_mut() = state.owner;
     });
     GOVERNANCE_CANISTER_ID.with(|id| {
-        *id.borrow_mut() = governance_canister_id;
+        *id.borrow_mut() = state.governance_canister_id;
+    });
+    USER_CANISTER_ID.with(|id| {
+        *id.borrow_mut() = state.user_canister_id;
+    });
+    NOTIFICATION_CANISTER_ID.with(|id| {
+        *id.borrow_mut() = state.notification_canister_id;
+    });
+    PENDING_LEGACY_GRANTS.with(|g| {
+        *g.borrow_mut() = state.pending_legacy_grants.unwrap_or_default();
     });
 }
 
@@ -158,7 +200,6 @@ fn init(initial_owner: Principal) {
     OWNER.with(|o| {
         *o.borrow_mut() = initial_owner;
     });
-    ADMINS.with(|a| a.borrow_mut().insert(initial_owner, ()));
 
     // Create default welcome post
     let default_post = GlobalPost {
@@ -174,6 +215,7 @@ This is your decentralized chatting network. Start exploring, and don't forget t
 Enjoy your journey!"#.to_string(),
         timestamp: time(),
         origin_sector_id: None,
+        content_hash: None,
     };
 
     POSTS.with(|p| {
@@ -185,11 +227,6 @@ Enjoy your journey!"#.to_string(),
     }); // Ensure the next post ID is correct
 }
 
-// Helper to check if a principal is an admin.
-fn is_admin(p: &Principal) -> bool {
-    ADMINS.with(|a| a.borrow().contains_key(p))
-}
-
 #[update]
 fn set_governance_canister(id: Principal) -> Result<(), String> {
     let caller = ic_cdk::api::caller();
@@ -202,15 +239,115 @@ fn set_governance_canister(id: Principal) -> Result<(), String> {
     Ok(())
 }
 
+#[update]
+fn set_user_canister(id: Principal) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if caller != OWNER.with(|o| *o.borrow()) {
+        return Err("Unauthorized: Only owner can set user canister ID.".to_string());
+    }
+    USER_CANISTER_ID.with(|user_id| {
+        *user_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
+#[update]
+fn set_notification_canister(id: Principal) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if caller != OWNER.with(|o| *o.borrow()) {
+        return Err("Unauthorized: Only owner can set notification canister ID.".to_string());
+    }
+    NOTIFICATION_CANISTER_ID.with(|notif_id| {
+        *notif_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
+// Hands the admins and global posters from the old in-canister lists to the user canister once
+// it is configured. Grants for users without a profile stay pending and are returned, so this can
+// be called again once they have signed up.
+#[update]
+async fn apply_legacy_grants() -> Result<Vec<(Principal, UserTag)>, String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::api::caller()) {
+        return Err("Unauthorized: Only controllers can apply legacy grants.".to_string());
+    }
+    let user_canister_id = USER_CANISTER_ID.with(|id| *id.borrow()).ok_or_else(||
+        "User canister not configured.".to_string()
+    )?;
+    let grants = PENDING_LEGACY_GRANTS.with(|g| g.borrow().clone());
+    if grants.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let (result,): (Result<Vec<(Principal, UserTag)>, Reserved>,) = ic_cdk::call(
+        user_canister_id,
+        "import_legacy_grants",
+        (grants,)
+    ).await.map_err(|(code, msg)| format!("Failed to import grants ({:?}): {}", code, msg))?;
+    let remaining = result.map_err(|_| "User canister refused the grants.".to_string())?;
+    PENDING_LEGACY_GRANTS.with(|g| {
+        *g.borrow_mut() = remaining.clone();
+    });
+    Ok(remaining)
+}
+
+// === Author Attribution ===
+
+// Resolves the author's username and highest platform tag from the user canister.
+// The user canister is the single source of truth for platform roles (Admin, GlobalPoster),
+// and client-supplied identity fields are never trusted for attribution.
+async fn resolve_author(author: Principal) -> Result<(String, UserTag), String> {
+    let user_canister_id = USER_CANISTER_ID.with(|id| *id.borrow()).ok_or_else(||
+        "User canister not configured.".to_string()
+    )?;
+
+    let profile = match
+        ic_cdk::call::<_, (Option<UserProfile>,)>(user_canister_id, "get_profile_by_principal", (
+            author,
+        )).await
+    {
+        Ok((Some(profile),)) => profile,
+        Ok((None,)) => {
+            return Err("Author does not have a profile.".to_string());
+        }
+        Err((code, msg)) => {
+            return Err(format!("Failed to get author profile ({:?}): {}", code, msg));
+        }
+    };
+
+    let tag = if profile.tags.contains(&UserTag::Admin) {
+        UserTag::Admin
+    } else if profile.tags.contains(&UserTag::GlobalPoster) {
+        UserTag::GlobalPoster
+    } else {
+        UserTag::User
+    };
+
+    Ok((profile.username, tag))
+}
+
+// Asks the origin sector for the author's role. A failed call leaves the role unset.
+async fn resolve_sector_role(sector_id: Principal, author: Principal) -> Option<SectorRole> {
+    match
+        ic_cdk::call::<_, (Option<SectorRole>,)>(sector_id, "get_member_role", (author,)).await
+    {
+        Ok((role,)) => role,
+        Err(_) => None,
+    }
+}
+
 // === Public Update Calls (Content Submission) ===
 
 #[update]
-fn submit_post_from_sector(post_data: SectorPostSubmission) -> Result<u64, String> {
+async fn submit_post_from_sector(post_data: SectorPostSubmission) -> Result<u64, String> {
     let caller = ic_cdk::api::caller();
     if !VETTED_SECTORS.with(|s| s.borrow().contains_key(&caller)) {
         return Err("Unauthorized: Calling canister is not a vetted sector.".to_string());
     }
 
+    let (author_username, author_tag) = resolve_author(post_data.author_principal).await?;
+    let author_sector_role = resolve_sector_role(caller, post_data.author_principal).await;
+
     let id = NEXT_POST_ID.with(|next_id| {
         let mut next_id = next_id.borrow_mut();
         let id = *next_id;
@@ -221,29 +358,39 @@ fn submit_post_from_sector(post_data: SectorPostSubmission) -> Result<u64, Strin
     let new_post = GlobalPost {
         id,
         author_principal: post_data.author_principal,
-        author_username: post_data.author_username,
-        author_user_tag: None,
-        author_sector_role: None,
+        author_username,
+        author_user_tag: Some(author_tag),
+        author_sector_role,
         content_markdown: post_data.content_markdown,
         timestamp: time(),
-        origin_sector_id: Some(post_data.origin_sector_id),
+        origin_sector_id: Some(caller),
+        content_hash: post_data.content_hash,
     };
 
     POSTS.with(|p| p.borrow_mut().insert(id, new_post));
+
+    // Let the author know their sector post made it out. Fire-and-forget.
+    if let Some(notification_canister_id) = NOTIFICATION_CANISTER_ID.with(|id| *id.borrow()) {
+        let payload = NotificationPayload {
+            kind: NotificationKind::GlobalPostPublished,
+            actor: None,
+            reference: Some(id.to_string()),
+            message: "Your post was published to the Global Feed.".to_string(),
+        };
+        let _ = ic_cdk::notify(notification_canister_id, "push_notification", (
+            vec![post_data.author_principal],
+            payload,
+        ));
+    }
     Ok(id)
 }
 
 #[update]
-fn submit_direct_post(
-    post_data: DirectPostSubmission,
-    author_username: String,
-    author_tag: UserTag
-) -> Result<u64, String> {
+async fn submit_direct_post(post_data: DirectPostSubmission) -> Result<u64, String> {
     let caller = ic_cdk::api::caller();
-    let is_admin = is_admin(&caller);
-    let is_global_poster = GLOBAL_POSTERS.with(|gp| gp.borrow().contains_key(&caller));
+    let (author_username, author_tag) = resolve_author(caller).await?;
 
-    if !is_admin && !is_global_poster {
+    if author_tag != UserTag::Admin && author_tag != UserTag::GlobalPoster {
         return Err("Unauthorized: Caller is not an admin or global poster.".to_string());
     }
 
@@ -263,12 +410,30 @@ fn submit_direct_post(
         content_markdown: post_data.content_markdown,
         timestamp: time(),
         origin_sector_id: None,
+        content_hash: None,
     };
 
     POSTS.with(|p| p.borrow_mut().insert(id, new_post));
     Ok(id)
 }
 
+// Called by the origin sector when an approved post is deleted or its author withdraws it.
+// Only the sector the post came from may retract it; vetting status is not required so that a
+// sector that has since lost its vetting can still take content down.
+#[update]
+fn retract_post_from_sector(global_post_id: u64) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    POSTS.with(|p| {
+        let mut posts = p.borrow_mut();
+        let post = posts.get(&global_post_id).ok_or_else(|| "Post not found.".to_string())?;
+        if post.origin_sector_id != Some(caller) {
+            return Err("Unauthorized: Caller is not the sector this post came from.".to_string());
+        }
+        posts.remove(&global_post_id);
+        Ok(())
+    })
+}
+
 // === Public Update Calls (ACL Management) ===
 
 #[update]
@@ -292,31 +457,66 @@ fn set_sector_vetted_status(sector_id: Principal, new_status: bool) -> Result<()
     Ok(())
 }
 
-#[update]
-fn add_global_poster(user: Principal) -> Result<(), String> {
-    if !is_admin(&ic_cdk::api::caller()) {
-        return Err("Unauthorized".to_string());
-    }
-    GLOBAL_POSTERS.with(|gp| gp.borrow_mut().insert(user, ()));
-    Ok(())
-}
+// === Public Update Calls (Account Lifecycle) ===
 
+// Called by the user canister when an account is deleted. Direct posts belong to the author and
+// are removed; posts that came from a sector belong to that community and are anonymized.
 #[update]
-fn remove_global_poster(user: Principal) -> Result<(), String> {
-    if !is_admin(&ic_cdk::api::caller()) {
-        return Err("Unauthorized".to_string());
+fn remove_author_content(author: Principal) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if Some(caller) != USER_CANISTER_ID.with(|id| *id.borrow()) {
+        return Err("Unauthorized: Caller is not the user canister.".to_string());
     }
-    GLOBAL_POSTERS.with(|gp| gp.borrow_mut().remove(&user));
+
+    POSTS.with(|p| {
+        let mut posts = p.borrow_mut();
+        posts.retain(|_, post| post.author_principal != author || post.origin_sector_id.is_some());
+        for post in posts.values_mut() {
+            if post.author_principal == author {
+                post.author_principal = Principal::anonymous();
+                post.author_username = "[deleted]".to_string();
+                post.author_user_tag = None;
+                post.author_sector_role = None;
+            }
+        }
+    });
     Ok(())
 }
 
 // === Public Query Calls ===
 
-#[query]
-fn get_global_feed(page: u64, size: u64) -> Vec<GlobalPost> {
+/// The caller's blocked and muted users, as resolved by the user canister. Nobody is hidden when
+/// the caller asks to see everything, or if the lookup fails rather than failing the query.
+async fn hidden_authors_of_caller(show_hidden: Option<bool>) -> HashSet<Principal> {
+    if show_hidden.unwrap_or(false) {
+        return HashSet::new();
+    }
+    let Some(user_canister_id) = USER_CANISTER_ID.with(|id| *id.borrow()) else {
+        return HashSet::new();
+    };
+    match
+        ic_cdk::call::<_, (Result<Vec<Principal>, Reserved>,)>(
+            user_canister_id,
+            "get_hidden_authors_of",
+            (ic_cdk::api::caller(),)
+        ).await
+    {
+        Ok((Ok(hidden),)) => hidden.into_iter().collect(),
+        _ => HashSet::new(),
+    }
+}
+
+// Posts by users the caller has blocked or muted are left out unless `show_hidden` is set.
+#[query(composite = true)]
+async fn get_global_feed(page: u64, size: u64, show_hidden: Option<bool>) -> Vec<GlobalPost> {
+    let excluded = hidden_authors_of_caller(show_hidden).await;
     POSTS.with(|p| {
         let posts_map = p.borrow();
-        let mut posts: Vec<_> = posts_map.values().cloned().collect();
+        let mut posts: Vec<_> = posts_map
+            .values()
+            .filter(|post| !excluded.contains(&post.author_principal))
+            .cloned()
+            .collect();
 
         // Sort by post ID, newest first (descending)
         posts.sort_by(|a, b| b.id.cmp(&a.id));
@@ -332,6 +532,20 @@ fn get_global_feed(page: u64, size: u64) -> Vec<GlobalPost> {
     })
 }
 
+#[query]
+fn get_posts_by_author(author: Principal) -> Vec<GlobalPost> {
+    POSTS.with(|p| {
+        let mut posts: Vec<_> = p
+            .borrow()
+            .values()
+            .filter(|post| post.author_principal == author)
+            .cloned()
+            .collect();
+        posts.sort_by(|a, b| b.id.cmp(&a.id));
+        posts
+    })
+}
+
 #[query]
 fn get_vetted_sectors() -> Vec<Principal> {
     VETTED_SECTORS.with(|s| s.borrow().keys().cloned().collect())
@@ -31,11 +31,28 @@ pub struct Vote {
 // Actor Interfaces for Inter-Canister Calls
 // Note: These are not stored in state but are used for deserializing inter-canister call responses.
 #[derive(CandidType, Deserialize, Clone)]
-pub struct UserProfile {
+pub struct ActivitySignal {
     created_at: u64,
-    last_seen_timestamp: u64,
+    last_active_day: u64,
 }
 
+// Mirrors the notification canister's interface (only the kinds this canister raises).
+#[derive(CandidType, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    VoteStarted,
+    VoteEnded,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct NotificationPayload {
+    kind: NotificationKind,
+    actor: Option<Principal>,
+    reference: Option<String>,
+    message: String,
+}
+
+const NOTIFICATION_BATCH_SIZE: usize = 200;
+
 // State
 thread_local! {
     // Stable State
@@ -46,6 +63,7 @@ thread_local! {
     // Canister Dependencies
     static USER_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
     static GLOBAL_FEED_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    static NOTIFICATION_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
 
     // Governance Parameters
     static VOTE_DURATION_NS: RefCell<u64> = RefCell::new(3 * 24 * 3_600 * 1_000_000_000); // 72 hours
@@ -70,6 +88,7 @@ struct StableState {
     max_account_inactivity_ns: u64,
     quorum_percentage: u64,
     majority_threshold_percentage: u64,
+    notification_canister_id: Option<Principal>,
 }
 
 // ==================================================================================================
@@ -90,6 +109,7 @@ fn pre_upgrade() {
         max_account_inactivity_ns: MAX_ACCOUNT_INACTIVITY_NS.with(|s| *s.borrow()),
         quorum_percentage: QUORUM_PERCENTAGE.with(|s| *s.borrow()),
         majority_threshold_percentage: MAJORITY_THRESHOLD_PERCENTAGE.with(|s| *s.borrow()),
+        notification_canister_id: NOTIFICATION_CANISTER_ID.with(|s| *s.borrow()),
     };
     ic_cdk::storage::stable_save((state,)).unwrap();
 }
@@ -130,6 +150,9 @@ fn post_upgrade() {
     MAJORITY_THRESHOLD_PERCENTAGE.with(|s| {
         *s.borrow_mut() = state.majority_threshold_percentage;
     });
+    NOTIFICATION_CANISTER_ID.with(|s| {
+        *s.borrow_mut() = state.notification_canister_id;
+    });
 }
 
 // ==================================================================================================
@@ -149,6 +172,17 @@ fn init(initial_owner: Principal, user_canister: Principal, global_feed_canister
     });
 }
 
+#[update]
+fn set_notification_canister(id: Principal) -> Result<(), String> {
+    if Some(caller()) != OWNER.with(|o| *o.borrow()) {
+        return Err("Unauthorized: Only owner can set notification canister ID.".to_string());
+    }
+    NOTIFICATION_CANISTER_ID.with(|notif_id| {
+        *notif_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
 // ==================================================================================================
 // === Public Query Calls ===
 // ==================================================================================================
@@ -216,6 +250,17 @@ async fn initiate_censor_vote(target_sector: Principal) -> Result<u64, String> {
     };
 
     VOTES.with(|v| v.borrow_mut().push(new_vote));
+
+    // Every eligible user may vote, so the opening is announced platform-wide.
+    if let Some(notification_canister_id) = NOTIFICATION_CANISTER_ID.with(|id| *id.borrow()) {
+        let payload = NotificationPayload {
+            kind: NotificationKind::VoteStarted,
+            actor: Some(initiator),
+            reference: Some(id.to_string()),
+            message: format!("A censor vote has started against sector {}.", target_sector),
+        };
+        let _ = ic_cdk::notify(notification_canister_id, "push_broadcast", (payload,));
+    }
     Ok(id)
 }
 
@@ -297,6 +342,38 @@ async fn tally_vote(vote_id: u64) -> Result<String, String> {
         vote.is_tallied = true;
     });
 
+    let outcome = conclude_vote(&vote_to_tally).await;
+
+    // Tell the initiator and everyone who took part how it ended.
+    if let Some(notification_canister_id) = NOTIFICATION_CANISTER_ID.with(|id| *id.borrow()) {
+        let mut recipients: Vec<Principal> = vote_to_tally.voters
+            .iter()
+            .map(|(voter, _)| *voter)
+            .collect();
+        recipients.push(vote_to_tally.initiator);
+        let payload = NotificationPayload {
+            kind: NotificationKind::VoteEnded,
+            actor: None,
+            reference: Some(vote_id.to_string()),
+            message: match &outcome {
+                Ok(summary) => summary.clone(),
+                Err(_) => "Vote passed, but its outcome could not be applied yet.".to_string(),
+            },
+        };
+        // The notification canister caps recipients per event.
+        for chunk in recipients.chunks(NOTIFICATION_BATCH_SIZE) {
+            let _ = ic_cdk::notify(notification_canister_id, "push_notification", (
+                chunk.to_vec(),
+                payload.clone(),
+            ));
+        }
+    }
+
+    outcome
+}
+
+// Applies quorum and majority rules to a closed vote and executes the outcome.
+async fn conclude_vote(vote_to_tally: &Vote) -> Result<String, String> {
     let total_votes = vote_to_tally.votes_for + vote_to_tally.votes_against;
 
     // Quorum check (using a placeholder for total active users)
@@ -337,17 +414,20 @@ async fn tally_vote(vote_id: u64) -> Result<String, String> {
 
 async fn check_voter_eligibility(voter: Principal) -> Result<(), String> {
     let canister_id = USER_CANISTER_ID.with(|id| id.borrow().expect("User Canister ID not set."));
-    let response: Result<(Option<UserProfile>,), _> = ic_cdk::call(
+    let response: Result<(Result<Option<ActivitySignal>, candid::Reserved>,), _> = ic_cdk::call(
         canister_id,
-        "get_profile_by_principal",
+        "get_activity_signal",
         (voter,)
     ).await;
 
-    let profile = match response {
-        Ok((Some(p),)) => p,
-        Ok((None,)) => {
+    let activity = match response {
+        Ok((Ok(Some(p)),)) => p,
+        Ok((Ok(None),)) => {
             return Err("Voter does not have a profile.".to_string());
         }
+        Ok((Err(_),)) => {
+            return Err("The user canister refused the activity lookup.".to_string());
+        }
         Err((code, msg)) => {
             return Err(format!("Failed to get profile ({:?}): {}", code, msg));
         }
@@ -358,13 +438,13 @@ async fn check_voter_eligibility(voter: Principal) -> Result<(), String> {
     let max_inactivity = MAX_ACCOUNT_INACTIVITY_NS.with(|i| *i.borrow());
 
     // Account Tenure
-    let account_age = now.saturating_sub(profile.created_at);
+    let account_age = now.saturating_sub(activity.created_at);
     if account_age < min_tenure {
         return Err("Account tenure is too new to vote.".to_string());
     }
 
-    // Account Activity
-    let inactivity_duration = now.saturating_sub(profile.last_seen_timestamp);
+    // Account Activity (the user canister only reports activity at day granularity)
+    let inactivity_duration = now.saturating_sub(activity.last_active_day);
     if inactivity_duration > max_inactivity {
         return Err("Account has been inactive for too long to vote.".to_string());
     }
@@ -0,0 +1,597 @@
+#![allow(warnings)]
+
+use candid::{ CandidType, Deserialize, Principal };
+use ic_cdk::{ api::{ caller, time }, call };
+use ic_cdk_macros::*;
+use std::cell::RefCell;
+use std::collections::{ HashMap, HashSet };
+
+// ==================================================================================================
+// === Types & State ===
+// ==================================================================================================
+
+// Public Types
+#[derive(CandidType, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NotificationKind {
+    Mention,
+    Reply,
+    RoleChanged,
+    Invite,
+    RekeyRequired,
+    VoteStarted,
+    VoteEnded,
+    GlobalPostPublished,
+    GlobalPostRejected,
+    JoinRequest, // A request to join a sector was received or decided
+}
+
+// What a source canister sends. The source itself is always taken from the caller.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct NotificationPayload {
+    kind: NotificationKind,
+    actor: Option<Principal>, // The user who triggered the event, if any
+    reference: Option<String>, // e.g. a post ID, vote ID or invite code
+    message: String,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Notification {
+    id: u64,
+    kind: NotificationKind,
+    source: Principal, // The canister that raised the event (a sector, the global feed, ...)
+    actor: Option<Principal>,
+    reference: Option<String>,
+    message: String,
+    created_at: u64,
+    is_read: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct NotificationPreferences {
+    mentions: bool,
+    replies: bool,
+    role_changes: bool,
+    invites: bool,
+    rekey_alerts: bool,
+    votes: bool,
+    global_feed: bool,
+    muted_sources: Vec<Principal>, // Sources (e.g. a noisy sector) the user never hears from
+}
+
+impl Default for NotificationPreferences {
+    fn default() -> Self {
+        Self {
+            mentions: true,
+            replies: true,
+            role_changes: true,
+            invites: true,
+            rekey_alerts: true,
+            votes: true,
+            global_feed: true,
+            muted_sources: Vec::new(),
+        }
+    }
+}
+
+// Custom Types for State
+#[derive(CandidType, Deserialize, Clone, Default)]
+struct Inbox {
+    notifications: Vec<Notification>, // Oldest first
+    read_broadcasts: HashSet<u64>,
+}
+
+// Which kind of canister pushed an event. Each one may only raise the events it owns.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SourceType {
+    Sector,
+    GlobalFeed,
+    Governance,
+    UserCanister,
+}
+
+// Custom Error Type
+#[derive(CandidType, Deserialize, Debug)]
+pub enum Error {
+    Unauthorized(String),
+    NotFound(String),
+    ConfigError(String),
+    CallFailed(String),
+    ValidationError(String),
+}
+
+// State Definition
+type InboxStore = HashMap<Principal, Inbox>;
+type PreferenceStore = HashMap<Principal, NotificationPreferences>;
+
+const MAX_INBOX_SIZE: usize = 200; // Oldest notifications are dropped beyond this
+const MAX_BROADCASTS: usize = 50;
+const MAX_RECIPIENTS_PER_EVENT: usize = 200;
+const MAX_MESSAGE_LENGTH: usize = 280;
+const MAX_REFERENCE_LENGTH: usize = 128;
+const MAX_PAGE_SIZE: usize = 50;
+const MAX_MUTED_SOURCES: usize = 100;
+
+thread_local! {
+    static INBOXES: RefCell<InboxStore> = RefCell::new(HashMap::new());
+    static PREFERENCES: RefCell<PreferenceStore> = RefCell::new(HashMap::new());
+    static BROADCASTS: RefCell<Vec<Notification>> = RefCell::new(Vec::new()); // Oldest first
+    static NEXT_NOTIFICATION_ID: RefCell<u64> = RefCell::new(0);
+    static OWNER: RefCell<Option<Principal>> = RefCell::new(None);
+
+    // Canister dependencies
+    static USER_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    static GLOBAL_FEED_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    static FACTORY_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    static GOVERNANCE_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+
+    // Cache of sectors confirmed by the factory. Not persisted; rebuilt on demand after upgrades.
+    static VERIFIED_SECTORS: RefCell<HashSet<Principal>> = RefCell::new(HashSet::new());
+}
+
+// Stable state for upgrades
+#[derive(CandidType, Deserialize)]
+struct StableState {
+    inboxes: InboxStore,
+    preferences: PreferenceStore,
+    broadcasts: Vec<Notification>,
+    next_notification_id: u64,
+    owner: Option<Principal>,
+    user_canister_id: Option<Principal>,
+    global_feed_canister_id: Option<Principal>,
+    factory_canister_id: Option<Principal>,
+    governance_canister_id: Option<Principal>,
+}
+
+// ==================================================================================================
+// === Upgrade Hooks ===
+// ==================================================================================================
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    let state = StableState {
+        inboxes: INBOXES.with(|s| s.borrow().clone()),
+        preferences: PREFERENCES.with(|s| s.borrow().clone()),
+        broadcasts: BROADCASTS.with(|s| s.borrow().clone()),
+        next_notification_id: NEXT_NOTIFICATION_ID.with(|s| *s.borrow()),
+        owner: OWNER.with(|s| *s.borrow()),
+        user_canister_id: USER_CANISTER_ID.with(|s| *s.borrow()),
+        global_feed_canister_id: GLOBAL_FEED_CANISTER_ID.with(|s| *s.borrow()),
+        factory_canister_id: FACTORY_CANISTER_ID.with(|s| *s.borrow()),
+        governance_canister_id: GOVERNANCE_CANISTER_ID.with(|s| *s.borrow()),
+    };
+    ic_cdk::storage::stable_save((state,)).unwrap();
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    let (state,): (StableState,) = ic_cdk::storage::stable_restore().unwrap();
+    INBOXES.with(|s| {
+        *s.borrow_mut() = state.inboxes;
+    });
+    PREFERENCES.with(|s| {
+        *s.borrow_mut() = state.preferences;
+    });
+    BROADCASTS.with(|s| {
+        *s.borrow_mut() = state.broadcasts;
+    });
+    NEXT_NOTIFICATION_ID.with(|s| {
+        *s.borrow_mut() = state.next_notification_id;
+    });
+    OWNER.with(|s| {
+        *s.borrow_mut() = state.owner;
+    });
+    USER_CANISTER_ID.with(|s| {
+        *s.borrow_mut() = state.user_canister_id;
+    });
+    GLOBAL_FEED_CANISTER_ID.with(|s| {
+        *s.borrow_mut() = state.global_feed_canister_id;
+    });
+    FACTORY_CANISTER_ID.with(|s| {
+        *s.borrow_mut() = state.factory_canister_id;
+    });
+    GOVERNANCE_CANISTER_ID.with(|s| {
+        *s.borrow_mut() = state.governance_canister_id;
+    });
+}
+
+// ==================================================================================================
+// === Initialization & Setup (Owner Only) ===
+// ==================================================================================================
+
+#[init]
+fn init(
+    initial_owner: Principal,
+    user_canister: Principal,
+    global_feed_canister: Principal,
+    factory_canister: Principal
+) {
+    OWNER.with(|o| {
+        *o.borrow_mut() = Some(initial_owner);
+    });
+    USER_CANISTER_ID.with(|id| {
+        *id.borrow_mut() = Some(user_canister);
+    });
+    GLOBAL_FEED_CANISTER_ID.with(|id| {
+        *id.borrow_mut() = Some(global_feed_canister);
+    });
+    FACTORY_CANISTER_ID.with(|id| {
+        *id.borrow_mut() = Some(factory_canister);
+    });
+}
+
+fn is_owner() -> Result<(), Error> {
+    let caller = caller();
+    OWNER.with(|o| {
+        match *o.borrow() {
+            Some(owner) if owner == caller => Ok(()),
+            _ => Err(Error::Unauthorized("Caller is not the owner.".to_string())),
+        }
+    })
+}
+
+#[update]
+fn set_user_canister(id: Principal) -> Result<(), Error> {
+    is_owner()?;
+    USER_CANISTER_ID.with(|user_id| {
+        *user_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
+#[update]
+fn set_global_feed_canister(id: Principal) -> Result<(), Error> {
+    is_owner()?;
+    GLOBAL_FEED_CANISTER_ID.with(|glo_id| {
+        *glo_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
+#[update]
+fn set_factory_canister(id: Principal) -> Result<(), Error> {
+    is_owner()?;
+    FACTORY_CANISTER_ID.with(|f_id| {
+        *f_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
+#[update]
+fn set_governance_canister(id: Principal) -> Result<(), Error> {
+    is_owner()?;
+    GOVERNANCE_CANISTER_ID.with(|gov_id| {
+        *gov_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
+// ==================================================================================================
+// === Helper Functions ===
+// ==================================================================================================
+
+/// Asks the factory whether a caller is a sector it created. Positive answers are cached.
+async fn is_legitimate_sector(sector_id: Principal) -> bool {
+    if VERIFIED_SECTORS.with(|v| v.borrow().contains(&sector_id)) {
+        return true;
+    }
+    let Some(factory_id) = FACTORY_CANISTER_ID.with(|id| *id.borrow()) else {
+        return false;
+    };
+
+    match call::<_, (bool,)>(factory_id, "is_registered_sector", (sector_id,)).await {
+        Ok((true,)) => {
+            VERIFIED_SECTORS.with(|v| v.borrow_mut().insert(sector_id));
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Identifies the calling canister, or fails if it is not allowed to raise notifications.
+async fn resolve_source(source: Principal) -> Result<SourceType, Error> {
+    if Some(source) == GLOBAL_FEED_CANISTER_ID.with(|id| *id.borrow()) {
+        return Ok(SourceType::GlobalFeed);
+    }
+    if Some(source) == GOVERNANCE_CANISTER_ID.with(|id| *id.borrow()) {
+        return Ok(SourceType::Governance);
+    }
+    if Some(source) == USER_CANISTER_ID.with(|id| *id.borrow()) {
+        return Ok(SourceType::UserCanister);
+    }
+    if is_legitimate_sector(source).await {
+        return Ok(SourceType::Sector);
+    }
+    Err(Error::Unauthorized("Caller is not a recognised notification source.".to_string()))
+}
+
+/// A sector cannot pretend to be governance, and vice versa.
+fn source_may_raise(source_type: SourceType, kind: NotificationKind) -> bool {
+    match source_type {
+        SourceType::Sector =>
+            matches!(
+                kind,
+                NotificationKind::Mention |
+                    NotificationKind::Reply |
+                    NotificationKind::RoleChanged |
+                    NotificationKind::Invite |
+                    NotificationKind::RekeyRequired |
+                    NotificationKind::GlobalPostRejected |
+                    NotificationKind::JoinRequest
+            ),
+        SourceType::GlobalFeed =>
+            matches!(kind, NotificationKind::Mention | NotificationKind::GlobalPostPublished),
+        SourceType::Governance =>
+            matches!(kind, NotificationKind::VoteStarted | NotificationKind::VoteEnded),
+        SourceType::UserCanister => kind == NotificationKind::RoleChanged,
+    }
+}
+
+fn validate_payload(payload: &NotificationPayload) -> Result<(), Error> {
+    if payload.message.trim().is_empty() || payload.message.chars().count() > MAX_MESSAGE_LENGTH {
+        return Err(
+            Error::ValidationError(
+                format!("Message must be between 1 and {} characters.", MAX_MESSAGE_LENGTH)
+            )
+        );
+    }
+    if payload.reference.as_ref().map_or(false, |r| r.len() > MAX_REFERENCE_LENGTH) {
+        return Err(Error::ValidationError("Reference is too long.".to_string()));
+    }
+    Ok(())
+}
+
+fn wants(preferences: &NotificationPreferences, notification: &Notification) -> bool {
+    if preferences.muted_sources.contains(&notification.source) {
+        return false;
+    }
+    match notification.kind {
+        NotificationKind::Mention => preferences.mentions,
+        NotificationKind::Reply => preferences.replies,
+        NotificationKind::RoleChanged => preferences.role_changes,
+        NotificationKind::Invite | NotificationKind::JoinRequest => preferences.invites,
+        NotificationKind::RekeyRequired => preferences.rekey_alerts,
+        NotificationKind::VoteStarted | NotificationKind::VoteEnded => preferences.votes,
+        NotificationKind::GlobalPostPublished | NotificationKind::GlobalPostRejected =>
+            preferences.global_feed,
+    }
+}
+
+fn preferences_of(user: Principal) -> NotificationPreferences {
+    PREFERENCES.with(|p| p.borrow().get(&user).cloned().unwrap_or_default())
+}
+
+fn next_notification_id() -> u64 {
+    NEXT_NOTIFICATION_ID.with(|next_id| {
+        let mut next_id = next_id.borrow_mut();
+        let id = *next_id;
+        *next_id += 1;
+        id
+    })
+}
+
+/// The viewer's personal notifications merged with platform-wide broadcasts, newest first.
+fn collect_inbox(viewer: Principal) -> Vec<Notification> {
+    let preferences = preferences_of(viewer);
+    let inbox = INBOXES.with(|i| i.borrow().get(&viewer).cloned().unwrap_or_default());
+
+    let broadcasts: Vec<Notification> = BROADCASTS.with(|b| {
+        b.borrow()
+            .iter()
+            .filter(|broadcast| wants(&preferences, broadcast))
+            .cloned()
+            .map(|mut broadcast| {
+                broadcast.is_read = inbox.read_broadcasts.contains(&broadcast.id);
+                broadcast
+            })
+            .collect()
+    });
+
+    let mut all: Vec<Notification> = inbox.notifications.into_iter().chain(broadcasts).collect();
+    // IDs come from a single counter, so they order personal and broadcast events together.
+    all.sort_by(|a, b| b.id.cmp(&a.id));
+    all
+}
+
+// ==================================================================================================
+// === Public Query Calls ===
+// ==================================================================================================
+
+#[query]
+fn get_my_notifications(page: usize, page_size: usize, unread_only: bool) -> Vec<Notification> {
+    let page_size = page_size.min(MAX_PAGE_SIZE);
+    collect_inbox(caller())
+        .into_iter()
+        .filter(|notification| !unread_only || !notification.is_read)
+        .skip(page.saturating_mul(page_size))
+        .take(page_size)
+        .collect()
+}
+
+#[query]
+fn get_unread_notification_count() -> u64 {
+    collect_inbox(caller())
+        .iter()
+        .filter(|notification| !notification.is_read)
+        .count() as u64
+}
+
+#[query]
+fn get_notification_preferences() -> NotificationPreferences {
+    preferences_of(caller())
+}
+
+// ==================================================================================================
+// === Inbox Management ===
+// ==================================================================================================
+
+#[update]
+fn mark_notifications_read(ids: Vec<u64>) -> Result<(), Error> {
+    let caller = caller();
+    let ids: HashSet<u64> = ids.into_iter().collect();
+    let live_broadcasts: HashSet<u64> = BROADCASTS.with(|b| {
+        b.borrow()
+            .iter()
+            .map(|broadcast| broadcast.id)
+            .collect()
+    });
+
+    INBOXES.with(|i| {
+        let mut inboxes = i.borrow_mut();
+        let inbox = inboxes.entry(caller).or_default();
+        for notification in inbox.notifications.iter_mut() {
+            if ids.contains(&notification.id) {
+                notification.is_read = true;
+            }
+        }
+        inbox.read_broadcasts.extend(ids.intersection(&live_broadcasts));
+        // Broadcasts that have rotated out no longer need a read marker.
+        inbox.read_broadcasts.retain(|id| live_broadcasts.contains(id));
+    });
+    Ok(())
+}
+
+#[update]
+fn mark_all_notifications_read() -> Result<(), Error> {
+    let caller = caller();
+    let broadcast_ids: HashSet<u64> = BROADCASTS.with(|b| {
+        b.borrow()
+            .iter()
+            .map(|broadcast| broadcast.id)
+            .collect()
+    });
+
+    INBOXES.with(|i| {
+        let mut inboxes = i.borrow_mut();
+        let inbox = inboxes.entry(caller).or_default();
+        for notification in inbox.notifications.iter_mut() {
+            notification.is_read = true;
+        }
+        inbox.read_broadcasts = broadcast_ids;
+    });
+    Ok(())
+}
+
+#[update]
+fn update_notification_preferences(preferences: NotificationPreferences) -> Result<(), Error> {
+    let caller = caller();
+    if caller == Principal::anonymous() {
+        return Err(Error::Unauthorized("Anonymous principal not allowed.".to_string()));
+    }
+    if preferences.muted_sources.len() > MAX_MUTED_SOURCES {
+        return Err(
+            Error::ValidationError(
+                format!("You can mute at most {} sources.", MAX_MUTED_SOURCES)
+            )
+        );
+    }
+    PREFERENCES.with(|p| p.borrow_mut().insert(caller, preferences));
+    Ok(())
+}
+
+// ==================================================================================================
+// === Event Intake (Canisters Only) ===
+// ==================================================================================================
+
+// Delivers one event to a list of users. Sources call this with `notify`, so a failure here never
+// blocks the action that raised the event. Users who opted out of the kind, or muted the source,
+// simply never receive it.
+#[update]
+async fn push_notification(
+    recipients: Vec<Principal>,
+    payload: NotificationPayload
+) -> Result<(), Error> {
+    let source = caller();
+    let source_type = resolve_source(source).await?;
+    if !source_may_raise(source_type, payload.kind) {
+        return Err(Error::Unauthorized("This source cannot raise that notification.".to_string()));
+    }
+    validate_payload(&payload)?;
+    if recipients.len() > MAX_RECIPIENTS_PER_EVENT {
+        return Err(
+            Error::ValidationError(
+                format!("An event can reach at most {} recipients.", MAX_RECIPIENTS_PER_EVENT)
+            )
+        );
+    }
+
+    let now = time();
+    let recipients: HashSet<Principal> = recipients.into_iter().collect();
+    for recipient in recipients {
+        // Nobody needs to be told about their own action.
+        if recipient == Principal::anonymous() || Some(recipient) == payload.actor {
+            continue;
+        }
+
+        let notification = Notification {
+            id: next_notification_id(),
+            kind: payload.kind,
+            source,
+            actor: payload.actor,
+            reference: payload.reference.clone(),
+            message: payload.message.clone(),
+            created_at: now,
+            is_read: false,
+        };
+        if !wants(&preferences_of(recipient), &notification) {
+            continue;
+        }
+
+        INBOXES.with(|i| {
+            let mut inboxes = i.borrow_mut();
+            let inbox = inboxes.entry(recipient).or_default();
+            inbox.notifications.push(notification);
+            if inbox.notifications.len() > MAX_INBOX_SIZE {
+                let overflow = inbox.notifications.len() - MAX_INBOX_SIZE;
+                inbox.notifications.drain(..overflow);
+            }
+        });
+    }
+    Ok(())
+}
+
+// Platform-wide events (e.g. a governance vote opening) reach every user without being copied
+// into each inbox. Only governance may broadcast.
+#[update]
+async fn push_broadcast(payload: NotificationPayload) -> Result<(), Error> {
+    let source = caller();
+    let source_type = resolve_source(source).await?;
+    if source_type != SourceType::Governance || !source_may_raise(source_type, payload.kind) {
+        return Err(Error::Unauthorized("This source cannot broadcast.".to_string()));
+    }
+    validate_payload(&payload)?;
+
+    let broadcast = Notification {
+        id: next_notification_id(),
+        kind: payload.kind,
+        source,
+        actor: payload.actor,
+        reference: payload.reference,
+        message: payload.message,
+        created_at: time(),
+        is_read: false,
+    };
+
+    BROADCASTS.with(|b| {
+        let mut broadcasts = b.borrow_mut();
+        broadcasts.push(broadcast);
+        if broadcasts.len() > MAX_BROADCASTS {
+            let overflow = broadcasts.len() - MAX_BROADCASTS;
+            broadcasts.drain(..overflow);
+        }
+    });
+    Ok(())
+}
+
+// Called by the user canister when an account is deleted.
+#[update]
+fn forget_user(user: Principal) -> Result<(), Error> {
+    if Some(caller()) != USER_CANISTER_ID.with(|id| *id.borrow()) {
+        return Err(Error::Unauthorized("Caller is not the user canister.".to_string()));
+    }
+    INBOXES.with(|i| i.borrow_mut().remove(&user));
+    PREFERENCES.with(|p| p.borrow_mut().remove(&user));
+    Ok(())
+}
+
+// Export the interface for the smart contract.
+ic_cdk::export_candid!();
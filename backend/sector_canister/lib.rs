@@ -1,10 +1,16 @@
 #![allow(warnings)]
 
-use candid::{ CandidType, Deserialize, Principal };
+use candid::{ CandidType, Decode, Deserialize, Encode, Principal, Reserved };
 use ic_cdk::{ api::{ caller, management_canister::main::raw_rand, time }, call };
 use ic_cdk_macros::*;
+use ic_stable_structures::memory_manager::{ MemoryId, MemoryManager, VirtualMemory };
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{ DefaultMemoryImpl, StableCell, StableLog, Storable };
+use sha2::{ Digest, Sha256 };
+use std::borrow::Cow;
 use std::cell::RefCell;
 use std::collections::{ HashMap, HashSet };
+use std::time::Duration;
 
 // ==================================================================================================
 // === Types & State ===
@@ -22,7 +28,12 @@ pub enum SectorRole {
 pub enum PostStatus {
     Private,
     PendingGlobal,
+    ScheduledGlobal, // Approved and confirmed, waiting for the author's chosen publication time
     ApprovedGlobal,
+    RejectedGlobal,
+    RetractedGlobal,
+    Publishing, // Approved and being sent to the global feed right now
+    RetractPending, // Published by mistake (withdrawn mid-publication); the copy still has to go
 }
 
 #[derive(CandidType, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
@@ -39,6 +50,8 @@ pub struct SectorConfig {
     is_private: bool,
     security_model: ChatSecurityModel,
     owner: Principal,
+    member_directory_visible: Option<bool>, // Lets regular members use `list_members` (default off)
+    join_requests_enabled: Option<bool>, // Private sectors only; off by default
 }
 
 #[derive(CandidType, Deserialize, Clone)]
@@ -51,6 +64,10 @@ pub struct SectorDetails {
     channels: Vec<String>,
     rekey_required: bool,
     current_key_epoch: u32,
+    unread_total: u64, // Unread messages across all channels
+    announcement: Option<Announcement>,
+    owner: Principal,
+    pending_owner: Option<Principal>, // Member who has been offered ownership but not yet accepted
 }
 
 #[derive(CandidType, Deserialize, Clone, Debug)]
@@ -67,6 +84,12 @@ pub struct Post {
     timestamp: u64,
     status: PostStatus,
     global_post_id: Option<u64>,
+    reactions: Option<Vec<ReactionCount>>, // Filled in by queries; reactions are stored separately
+    edited_at: Option<u64>,
+    rejection_reason: Option<String>, // Set when a moderator rejects a global feed submission
+    content_hash: Option<Vec<u8>>, // SHA-256 of the plaintext, required for global posts; author-only
+    author_confirmed_at: Option<u64>, // Author signed off on the current text for the global feed
+    publish_at: Option<u64>, // Earliest global publication time requested by the author
 }
 
 #[derive(CandidType, Deserialize, Clone)]
@@ -76,6 +99,243 @@ pub struct Message {
     author_principal: Principal,
     timestamp: u64,
     encrypted_content_markdown: Vec<u8>,
+    // Threading. All optional so messages stored before threads existed still decode.
+    reply_to: Option<String>, // The message being replied to (and quoted)
+    thread_id: Option<String>, // The root message of the thread; None for root messages
+    reply_count: Option<u64>, // Set on root messages once they have replies
+    reactions: Option<Vec<ReactionCount>>, // Filled in by queries; reactions are stored separately
+}
+
+// Aggregated reactions on a post or message. In E2EE sectors the reaction is an encrypted payload,
+// so every payload is its own entry and clients decrypt and merge them.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ReactionCount {
+    reaction: Vec<u8>,
+    count: u64,
+    reacted_by_me: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub enum ReactionTarget {
+    Post(String),
+    Message {
+        channel_name: String,
+        message_id: String,
+    },
+}
+
+// Sector-wide announcement set by moderators. Encrypted like a message in E2EE sectors.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Announcement {
+    content: Vec<u8>,
+    key_epoch_id: u32,
+    posted_by: Principal,
+    posted_at: u64,
+}
+
+// A non-member asking to be let into a private sector.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct JoinRequest {
+    requester: Principal,
+    message: String,
+    requested_at: u64,
+}
+
+// One row of the member directory.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct MemberListEntry {
+    principal: Principal,
+    role: SectorRole,
+    custom_role: Option<String>,
+    joined_at: Option<u64>,
+    username: Option<String>, // Resolved from the user canister; None if that lookup fails
+}
+
+// A named set of permissions moderators can hand to members, e.g. chat helpers. See the
+// `PERM_*` constants for the bit layout.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct CustomRole {
+    name: String,
+    permissions: u32,
+}
+
+// An ownership offer from the current owner, waiting for the recipient to accept.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct OwnershipTransfer {
+    to: Principal,
+    initiated_at: u64,
+}
+
+// Moderator actions recorded in the sector's audit log.
+#[derive(CandidType, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AuditAction {
+    RoleChanged,
+    MemberKicked,
+    MemberInvited,
+    InviteCodeCreated,
+    ConfigUpdated,
+    ChannelCreated,
+    KeyRotated,
+    GlobalPostApproved,
+    GlobalPostRejected,
+    PostDeleted,
+    MessagePinned,
+    MessageUnpinned,
+    AnnouncementSet,
+    AnnouncementCleared,
+    OwnershipTransferStarted,
+    OwnershipTransferCancelled,
+    OwnershipTransferred,
+    CustomRoleUpdated,
+    CustomRoleDeleted,
+    CustomRoleAssigned,
+    JoinRequestApproved,
+    JoinRequestDenied,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct AuditEntry {
+    id: u64,
+    actor: Principal,
+    action: AuditAction,
+    target: Option<String>, // Member principal, post ID or channel name, depending on the action
+    details: Option<String>,
+    timestamp: u64,
+}
+
+// What an audit entry is about, as passed to `record_audit`.
+enum AuditTarget {
+    Member(Principal),
+    Item(String), // Post ID, channel name or role name
+}
+
+// Audit entries as stored in stable memory. Members are recorded by pseudonym, so forgetting a
+// member only drops their pseudonym and the log itself is never rewritten.
+#[derive(CandidType, Deserialize, Clone)]
+enum LoggedAuditTarget {
+    Member(u64),
+    Item(String),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct LoggedAuditEntry {
+    actor: u64,
+    action: AuditAction,
+    target: Option<LoggedAuditTarget>,
+    details: Option<String>,
+    timestamp: u64,
+}
+
+impl Storable for LoggedAuditEntry {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).unwrap()
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        Encode!(&self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Pin {
+    message_id: String,
+    pinned_by: Principal,
+    pinned_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct PinnedMessage {
+    message: Message,
+    pinned_by: Principal,
+    pinned_at: u64,
+}
+
+// A thread's root message together with a page of its replies (oldest first).
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Thread {
+    root: Message,
+    replies: Vec<Message>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ChannelUnread {
+    channel_name: String,
+    unread_count: u64,
+    last_read_at: Option<u64>, // Timestamp of the last message the member marked read
+}
+
+// Where a member was mentioned. Messages carry the channel they were sent in.
+#[derive(CandidType, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub enum MentionLocation {
+    Post,
+    Message(String),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Mention {
+    id: u64, // Increasing; used as the pagination cursor
+    item_id: String, // Post or message ID
+    location: MentionLocation,
+    author_principal: Principal,
+    timestamp: u64,
+}
+
+// Data Export Types (consumed by the user canister's `export_my_data`)
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ExportedPost {
+    id: String,
+    encrypted_content_markdown: Vec<u8>,
+    timestamp: u64,
+    status: String,
+    global_post_id: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ExportedMessage {
+    id: String,
+    channel_name: String,
+    key_epoch_id: u32,
+    timestamp: u64,
+    encrypted_content_markdown: Vec<u8>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct MemberDataExport {
+    role: Option<String>,
+    posts: Vec<ExportedPost>,
+    messages: Vec<ExportedMessage>,
+}
+
+// Notification Types (mirror the notification canister's interface)
+#[derive(CandidType, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NotificationKind {
+    Mention,
+    Reply,
+    RoleChanged,
+    Invite,
+    RekeyRequired,
+    GlobalPostRejected,
+    JoinRequest,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct NotificationPayload {
+    kind: NotificationKind,
+    actor: Option<Principal>,
+    reference: Option<String>,
+    message: String,
+}
+
+// Device key (mirrors the user canister's `DeviceKey`; only the fields used here)
+#[derive(CandidType, Deserialize, Clone)]
+pub struct DeviceKey {
+    id: u32,
 }
 
 // Custom Types for State
@@ -83,12 +343,15 @@ pub struct Message {
 struct Member {
     principal: Principal,
     role: SectorRole,
+    custom_role: Option<String>, // Grants permissions on top of the base role
+    joined_at: Option<u64>, // Unknown for members who joined before it was recorded
 }
 
 #[derive(CandidType, Deserialize, Clone)]
 struct Channel {
     name: String,
     messages: HashMap<String, Message>, // Keyed by Message ID
+    pins: Option<Vec<Pin>>, // Oldest pin first; optional so older channel state still decodes
 }
 
 // Custom Error Type
@@ -109,20 +372,90 @@ pub struct SectorConfigUpdate {
     name: String,
     abbreviation: String,
     description: String,
+    member_directory_visible: Option<bool>, // Left unchanged when omitted
+    join_requests_enabled: Option<bool>, // Left unchanged when omitted
 }
 
 // State Definition
 type MemberStore = HashMap<Principal, Member>;
 type PostStore = HashMap<String, Post>; // Keyed by Post ID
 type ChannelStore = HashMap<String, Channel>; // Keyed by channel name
+type MentionIndex = HashMap<Principal, Vec<Mention>>; // Member -> Mentions (oldest first)
+type ReadCursorStore = HashMap<Principal, HashMap<String, u64>>; // Member -> Channel -> Timestamp
+type ReactionStore = HashMap<String, HashMap<Vec<u8>, HashSet<Principal>>>; // Item ID -> Reactions
+type ScheduledPostStore = HashMap<String, String>; // Post ID -> Approved plaintext to publish
+type Memory = VirtualMemory<DefaultMemoryImpl>;
 
 const HIGH_SECURITY_MEMBER_LIMIT: usize = 50;
+const PRESENCE_TTL_NS: u64 = 2 * 60 * 1_000_000_000; // 2 minutes without a heartbeat = offline
+const MAX_MENTIONS_PER_ITEM: usize = 20;
+const MAX_MENTIONS_PER_MEMBER: usize = 500; // Oldest mentions are dropped beyond this
+const MENTIONS_PAGE_SIZE: usize = 50;
+const MEMBERS_PAGE_SIZE: usize = 50;
+const AUDIT_LOG_PAGE_SIZE: usize = 50;
+const THREAD_PAGE_SIZE: usize = 50;
+const MAX_REACTION_BYTES: usize = 32; // An emoji or short shortcode
+const MAX_ENCRYPTED_REACTION_BYTES: usize = 256;
+const MAX_REACTIONS_PER_USER: usize = 20; // Per post or message
+const MAX_PINS_PER_CHANNEL: usize = 10;
+const MAX_ANNOUNCEMENT_BYTES: usize = 4096;
+const MAX_CUSTOM_ROLES: usize = 20;
+const MAX_JOIN_REQUESTS: usize = 500;
+const MAX_JOIN_REQUEST_MESSAGE_LENGTH: usize = 500;
+// Every request notifies all reviewers, so denied or withdrawn requests cannot be repeated at once.
+const JOIN_REQUEST_DENIED_COOLDOWN_NS: u64 = 7 * 24 * 3_600 * 1_000_000_000; // 7 days
+const JOIN_REQUEST_CANCELLED_COOLDOWN_NS: u64 = 24 * 3_600 * 1_000_000_000; // 1 day
+const MAX_CUSTOM_ROLE_NAME_LENGTH: usize = 32;
+
+// Permission bits. Moderators hold all of them, Posters hold PERM_POST_TO_FEED, and custom roles
+// add any subset on top of a member's base role. Sector configuration, announcements, role
+// management and the audit log stay with the Moderator role.
+const PERM_MANAGE_CHANNELS: u32 = 1 << 0;
+const PERM_MANAGE_MEMBERS: u32 = 1 << 1;
+const PERM_APPROVE_GLOBAL_POSTS: u32 = 1 << 2;
+const PERM_ROTATE_KEYS: u32 = 1 << 3;
+const PERM_CREATE_INVITES: u32 = 1 << 4;
+const PERM_POST_TO_FEED: u32 = 1 << 5;
+const PERM_PIN_MESSAGES: u32 = 1 << 6;
+const ALL_PERMISSIONS: u32 = (1 << 7) - 1;
+// Key rotation wraps the new key for every member, so it needs the member list too.
+const LIST_MEMBERS_PERMISSIONS: u32 = PERM_MANAGE_MEMBERS | PERM_ROTATE_KEYS;
+const MAX_REJECTION_REASON_LENGTH: usize = 500;
+const CONTENT_HASH_LENGTH: usize = 32; // SHA-256
+const SCHEDULED_PUBLISH_RETRY: Duration = Duration::from_secs(5 * 60);
+
+const UPGRADE_STATE_MEMORY_ID: MemoryId = MemoryId::new(0);
+const AUDIT_LOG_INDEX_MEMORY_ID: MemoryId = MemoryId::new(1);
+const AUDIT_LOG_DATA_MEMORY_ID: MemoryId = MemoryId::new(2);
 
 thread_local! {
     static CONFIG: RefCell<Option<SectorConfig>> = RefCell::new(None);
     static MEMBERS: RefCell<MemberStore> = RefCell::new(HashMap::new());
     static POSTS: RefCell<PostStore> = RefCell::new(HashMap::new());
     static CHANNELS: RefCell<ChannelStore> = RefCell::new(HashMap::new());
+    static MENTIONS: RefCell<MentionIndex> = RefCell::new(HashMap::new());
+    static NEXT_MENTION_ID: RefCell<u64> = RefCell::new(0);
+    static READ_CURSORS: RefCell<ReadCursorStore> = RefCell::new(HashMap::new());
+    static REACTIONS: RefCell<ReactionStore> = RefCell::new(HashMap::new());
+    static ANNOUNCEMENT: RefCell<Option<Announcement>> = RefCell::new(None);
+    static SCHEDULED_POSTS: RefCell<ScheduledPostStore> = RefCell::new(HashMap::new());
+    static AUDIT_PSEUDONYMS: RefCell<HashMap<Principal, u64>> = RefCell::new(HashMap::new());
+    static NEXT_AUDIT_PSEUDONYM: RefCell<u64> = RefCell::new(0);
+    static PENDING_OWNERSHIP_TRANSFER: RefCell<Option<OwnershipTransfer>> = RefCell::new(None);
+    static CUSTOM_ROLES: RefCell<HashMap<String, CustomRole>> = RefCell::new(HashMap::new());
+    static JOIN_REQUESTS: RefCell<HashMap<Principal, JoinRequest>> = RefCell::new(HashMap::new());
+    // Requester -> Time before which they may not ask again
+    static JOIN_REQUEST_COOLDOWNS: RefCell<HashMap<Principal, u64>> = RefCell::new(HashMap::new());
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
+        MemoryManager::init(DefaultMemoryImpl::default())
+    );
+    // Append-only; entry IDs are positions in the log.
+    static AUDIT_LOG: RefCell<StableLog<LoggedAuditEntry, Memory, Memory>> = RefCell::new(
+        StableLog::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(AUDIT_LOG_INDEX_MEMORY_ID)),
+            MEMORY_MANAGER.with(|m| m.borrow().get(AUDIT_LOG_DATA_MEMORY_ID))
+        )
+    );
     static CRYPTO_STATE: RefCell<CryptoState> = RefCell::new(CryptoState {
         rekey_required: false,
         current_key_epoch: 1,
@@ -132,6 +465,12 @@ thread_local! {
     static INVITE_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
     static GLOBAL_FEED_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
     static USER_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    static NOTIFICATION_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    // The factory that installed this sector and shares control of it
+    static FACTORY_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+
+    // Ephemeral presence: Member -> Last Heartbeat. Deliberately not persisted across upgrades.
+    static PRESENCE: RefCell<HashMap<Principal, u64>> = RefCell::new(HashMap::new());
 }
 
 // Stable state for upgrades
@@ -145,6 +484,21 @@ struct StableState {
     invite_canister_id: Option<Principal>,
     global_feed_canister_id: Option<Principal>,
     user_canister_id: Option<Principal>,
+    notification_canister_id: Option<Principal>,
+    mentions: Option<MentionIndex>, // Optional so state saved before mentions existed still loads
+    next_mention_id: Option<u64>,
+    read_cursors: Option<ReadCursorStore>,
+    reactions: Option<ReactionStore>,
+    announcement: Option<Announcement>,
+    scheduled_posts: Option<ScheduledPostStore>,
+    audit_log: Option<Vec<AuditEntry>>, // Only set in state saved before the log moved out of it
+    audit_pseudonyms: Option<HashMap<Principal, u64>>,
+    next_audit_pseudonym: Option<u64>,
+    pending_ownership_transfer: Option<OwnershipTransfer>,
+    factory_canister_id: Option<Principal>, // Unknown for sectors created before it was recorded
+    custom_roles: Option<HashMap<String, CustomRole>>,
+    join_requests: Option<HashMap<Principal, JoinRequest>>,
+    join_request_cooldowns: Option<HashMap<Principal, u64>>,
 }
 
 // ==================================================================================================
@@ -156,6 +510,344 @@ fn bytes_to_hex_string(bytes: &[u8]) -> String {
     bytes.iter().map(|b| format!("{:02x}", b)).collect()
 }
 
+/// The caller's blocked and muted users, resolved by the user canister so feeds don't depend on
+/// the client to filter them. Nobody is hidden when the caller asks to see everything, and if the
+/// lookup fails nobody is hidden rather than failing the query.
+async fn hidden_authors_of_caller(show_hidden: Option<bool>) -> HashSet<Principal> {
+    if show_hidden.unwrap_or(false) {
+        return HashSet::new();
+    }
+    let Some(user_canister_id) = USER_CANISTER_ID.with(|id| *id.borrow()) else {
+        return HashSet::new();
+    };
+    match
+        call::<_, (Result<Vec<Principal>, Reserved>,)>(
+            user_canister_id,
+            "get_hidden_authors_of",
+            (caller(),)
+        ).await
+    {
+        Ok((Ok(hidden),)) => hidden.into_iter().collect(),
+        _ => HashSet::new(),
+    }
+}
+
+/// Tells the user canister that a member joined or left so their sector list stays accurate.
+/// Fire-and-forget: a failed notification never blocks the membership change itself.
+fn notify_membership_change(user: Principal, is_member: bool) {
+    if let Some(user_canister_id) = USER_CANISTER_ID.with(|id| *id.borrow()) {
+        let _ = ic_cdk::notify(user_canister_id, "record_sector_membership", (
+            ic_cdk::id(),
+            user,
+            is_member,
+        ));
+    }
+}
+
+/// Pushes an event to the notification canister. Fire-and-forget, like membership changes.
+fn notify_users(
+    recipients: Vec<Principal>,
+    kind: NotificationKind,
+    actor: Option<Principal>,
+    reference: Option<String>,
+    message: String
+) {
+    if recipients.is_empty() {
+        return;
+    }
+    if let Some(notification_canister_id) = NOTIFICATION_CANISTER_ID.with(|id| *id.borrow()) {
+        let payload = NotificationPayload { kind, actor, reference, message };
+        let _ = ic_cdk::notify(notification_canister_id, "push_notification", (
+            recipients,
+            payload,
+        ));
+    }
+}
+
+/// Appends an entry to the sector's audit log. `actor` is passed in rather than read from
+/// `caller()` so that entries written after an inter-canister call stay correct.
+fn record_audit(
+    actor: Principal,
+    action: AuditAction,
+    target: Option<AuditTarget>,
+    details: Option<String>
+) {
+    let target = target.map(|target| match target {
+        AuditTarget::Member(member) => LoggedAuditTarget::Member(audit_pseudonym(member)),
+        AuditTarget::Item(item) => LoggedAuditTarget::Item(item),
+    });
+    append_audit_entry(LoggedAuditEntry {
+        actor: audit_pseudonym(actor),
+        action,
+        target,
+        details,
+        timestamp: time(),
+    });
+}
+
+fn append_audit_entry(entry: LoggedAuditEntry) {
+    AUDIT_LOG.with(|log| log.borrow().append(&entry).expect("Failed to grow the audit log"));
+}
+
+/// The pseudonym standing for `user` in the audit log, assigning a new one if needed.
+fn audit_pseudonym(user: Principal) -> u64 {
+    AUDIT_PSEUDONYMS.with(|p| {
+        *p.borrow_mut()
+            .entry(user)
+            .or_insert_with(|| {
+                NEXT_AUDIT_PSEUDONYM.with(|next| {
+                    let mut next = next.borrow_mut();
+                    *next += 1;
+                    *next
+                })
+            })
+    })
+}
+
+/// Converts an entry from state saved before the audit log moved to stable memory.
+fn log_legacy_audit_entry(entry: AuditEntry) {
+    let target = entry.target.map(|target| match Principal::from_text(&target) {
+        Ok(member) => LoggedAuditTarget::Member(audit_pseudonym(member)),
+        Err(_) => LoggedAuditTarget::Item(target),
+    });
+    append_audit_entry(LoggedAuditEntry {
+        actor: audit_pseudonym(entry.actor),
+        action: entry.action,
+        target,
+        details: entry.details,
+        timestamp: entry.timestamp,
+    });
+}
+
+/// Whether stable memory is laid out by the memory manager rather than a single `stable_save`.
+fn uses_memory_manager() -> bool {
+    if ic_cdk::api::stable::stable_size() == 0 {
+        return false;
+    }
+    let mut magic = [0u8; 3];
+    ic_cdk::api::stable::stable_read(0, &mut magic);
+    &magic == b"MGR"
+}
+
+fn sector_name() -> String {
+    CONFIG.with(|c| c.borrow().as_ref().map(|config| config.name.clone())).unwrap_or_default()
+}
+
+/// Flags that the E2EE sector key must be rotated and lets the moderators know.
+fn require_rekey() {
+    CRYPTO_STATE.with(|cs| {
+        cs.borrow_mut().rekey_required = true;
+    });
+
+    let moderators: Vec<Principal> = MEMBERS.with(|m| {
+        m.borrow()
+            .values()
+            .filter(|member| permissions_of(member) & PERM_ROTATE_KEYS != 0)
+            .map(|member| member.principal)
+            .collect()
+    });
+    notify_users(
+        moderators,
+        NotificationKind::RekeyRequired,
+        None,
+        None,
+        format!("Membership of {} changed. Rotate the sector key to resume chat.", sector_name())
+    );
+}
+
+/// Keeps only mentions of current members other than the author. Content is opaque (and may be
+/// encrypted), so the client declares who it mentions alongside it.
+fn validate_mentions(mentions: Vec<Principal>, author: Principal) -> Result<Vec<Principal>, Error> {
+    if mentions.len() > MAX_MENTIONS_PER_ITEM {
+        return Err(
+            Error::ValidationError(
+                format!("At most {} members can be mentioned at once.", MAX_MENTIONS_PER_ITEM)
+            )
+        );
+    }
+    let unique: HashSet<Principal> = mentions.into_iter().collect();
+    Ok(
+        MEMBERS.with(|m| {
+            let members = m.borrow();
+            unique
+                .into_iter()
+                .filter(|p| *p != author && members.contains_key(p))
+                .collect()
+        })
+    )
+}
+
+/// Adds the item to each mentioned member's index and pings them through their inbox.
+fn record_mentions(
+    mentioned: Vec<Principal>,
+    item_id: &str,
+    location: MentionLocation,
+    author: Principal
+) {
+    if mentioned.is_empty() {
+        return;
+    }
+    let now = time();
+    MENTIONS.with(|m| {
+        let mut index = m.borrow_mut();
+        for member in mentioned.iter() {
+            let id = NEXT_MENTION_ID.with(|next_id| {
+                let mut next_id = next_id.borrow_mut();
+                let id = *next_id;
+                *next_id += 1;
+                id
+            });
+            let mentions = index.entry(*member).or_default();
+            mentions.push(Mention {
+                id,
+                item_id: item_id.to_string(),
+                location: location.clone(),
+                author_principal: author,
+                timestamp: now,
+            });
+            if mentions.len() > MAX_MENTIONS_PER_MEMBER {
+                let overflow = mentions.len() - MAX_MENTIONS_PER_MEMBER;
+                mentions.drain(..overflow);
+            }
+        }
+    });
+
+    let message = match &location {
+        MentionLocation::Post => format!("You were mentioned in a post in {}.", sector_name()),
+        MentionLocation::Message(channel) =>
+            format!("You were mentioned in #{} in {}.", channel, sector_name()),
+    };
+    notify_users(
+        mentioned,
+        NotificationKind::Mention,
+        Some(author),
+        Some(item_id.to_string()),
+        message
+    );
+}
+
+/// Per-channel unread counts for a member: top-level messages from others newer than their cursor.
+fn unread_counts_for(member: Principal) -> Vec<ChannelUnread> {
+    let cursors = READ_CURSORS.with(|r| r.borrow().get(&member).cloned().unwrap_or_default());
+    CHANNELS.with(|c| {
+        c.borrow()
+            .values()
+            .map(|channel| {
+                let last_read_at = cursors.get(&channel.name).cloned();
+                // Thread replies are read inside their thread, not via the channel cursor.
+                let unread_count = channel.messages
+                    .values()
+                    .filter(|msg| msg.thread_id.is_none())
+                    .filter(|msg| msg.author_principal != member)
+                    .filter(|msg| last_read_at.map_or(true, |cursor| msg.timestamp > cursor))
+                    .count() as u64;
+                ChannelUnread { channel_name: channel.name.clone(), unread_count, last_read_at }
+            })
+            .collect()
+    })
+}
+
+/// Aggregates the stored reactions on an item for `viewer`. `None` when nobody has reacted.
+fn reaction_counts(item_id: &str, viewer: Principal) -> Option<Vec<ReactionCount>> {
+    REACTIONS.with(|r| {
+        r.borrow()
+            .get(item_id)
+            .map(|reactions| {
+                reactions
+                    .iter()
+                    .map(|(reaction, users)| ReactionCount {
+                        reaction: reaction.clone(),
+                        count: users.len() as u64,
+                        reacted_by_me: users.contains(&viewer),
+                    })
+                    .collect()
+            })
+    })
+}
+
+/// What `viewer` may see of a post. The content hash is a plain SHA-256 of the plaintext that
+/// would let anyone confirm a guess at encrypted content, so only the author gets it back.
+/// Moderation state (a pending, scheduled or rejected global submission and its rejection
+/// reason) is shown to the author and the sector's reviewers.
+fn redact_post_for(mut post: Post, viewer: Principal, is_reviewer: bool) -> Post {
+    if post.author_principal != viewer {
+        post.content_hash = None;
+    }
+    if post.author_principal != viewer && !is_reviewer {
+        if
+            matches!(
+                post.status,
+                PostStatus::PendingGlobal |
+                    PostStatus::ScheduledGlobal |
+                    PostStatus::Publishing |
+                    PostStatus::RejectedGlobal
+            )
+        {
+            post.status = PostStatus::Private;
+        }
+        post.rejection_reason = None;
+        post.author_confirmed_at = None;
+        post.publish_at = None;
+    }
+    post
+}
+
+fn with_post_reactions(mut post: Post, viewer: Principal) -> Post {
+    post.reactions = reaction_counts(&post.id, viewer);
+    post
+}
+
+fn with_message_reactions(mut msg: Message, viewer: Principal) -> Message {
+    msg.reactions = reaction_counts(&msg.id, viewer);
+    msg
+}
+
+/// Resolves a reaction target to the ID it is stored under, failing if it does not exist.
+fn reaction_item_id(target: &ReactionTarget) -> Result<String, Error> {
+    let exists = match target {
+        ReactionTarget::Post(post_id) => POSTS.with(|p| p.borrow().contains_key(post_id)),
+        ReactionTarget::Message { channel_name, message_id } =>
+            CHANNELS.with(|c| {
+                c.borrow()
+                    .get(channel_name)
+                    .map_or(false, |channel| channel.messages.contains_key(message_id))
+            }),
+    };
+    if !exists {
+        return Err(Error::NotFound("Reaction target not found.".to_string()));
+    }
+    Ok(match target {
+        ReactionTarget::Post(post_id) => post_id.clone(),
+        ReactionTarget::Message { message_id, .. } => message_id.clone(),
+    })
+}
+
+/// Standard sectors take a short UTF-8 reaction; E2EE sectors take an opaque encrypted payload.
+fn validate_reaction(reaction: &[u8]) -> Result<(), Error> {
+    let is_e2ee = CONFIG.with(|c| {
+        c.borrow()
+            .as_ref()
+            .map_or(false, |config| config.security_model == ChatSecurityModel::HighSecurityE2EE)
+    });
+    if reaction.is_empty() {
+        return Err(Error::ValidationError("Reaction cannot be empty.".to_string()));
+    }
+    if is_e2ee {
+        if reaction.len() > MAX_ENCRYPTED_REACTION_BYTES {
+            return Err(Error::ValidationError("Encrypted reaction is too large.".to_string()));
+        }
+    } else {
+        if reaction.len() > MAX_REACTION_BYTES || std::str::from_utf8(reaction).is_err() {
+            return Err(
+                Error::ValidationError(
+                    format!("Reaction must be UTF-8 text of at most {} bytes.", MAX_REACTION_BYTES)
+                )
+            );
+        }
+    }
+    Ok(())
+}
+
 // ==================================================================================================
 // === Upgrade Hooks ===
 // ==================================================================================================
@@ -171,13 +863,38 @@ fn pre_upgrade() {
         invite_canister_id: INVITE_CANISTER_ID.with(|s| s.borrow().clone()),
         global_feed_canister_id: GLOBAL_FEED_CANISTER_ID.with(|s| s.borrow().clone()),
         user_canister_id: USER_CANISTER_ID.with(|s| s.borrow().clone()),
+        notification_canister_id: NOTIFICATION_CANISTER_ID.with(|s| s.borrow().clone()),
+        mentions: Some(MENTIONS.with(|s| s.borrow().clone())),
+        next_mention_id: Some(NEXT_MENTION_ID.with(|s| *s.borrow())),
+        read_cursors: Some(READ_CURSORS.with(|s| s.borrow().clone())),
+        reactions: Some(REACTIONS.with(|s| s.borrow().clone())),
+        announcement: ANNOUNCEMENT.with(|s| s.borrow().clone()),
+        scheduled_posts: Some(SCHEDULED_POSTS.with(|s| s.borrow().clone())),
+        audit_log: None,
+        audit_pseudonyms: Some(AUDIT_PSEUDONYMS.with(|s| s.borrow().clone())),
+        next_audit_pseudonym: Some(NEXT_AUDIT_PSEUDONYM.with(|s| *s.borrow())),
+        pending_ownership_transfer: PENDING_OWNERSHIP_TRANSFER.with(|s| s.borrow().clone()),
+        factory_canister_id: FACTORY_CANISTER_ID.with(|s| *s.borrow()),
+        custom_roles: Some(CUSTOM_ROLES.with(|s| s.borrow().clone())),
+        join_requests: Some(JOIN_REQUESTS.with(|s| s.borrow().clone())),
+        join_request_cooldowns: Some(JOIN_REQUEST_COOLDOWNS.with(|s| s.borrow().clone())),
     };
-    ic_cdk::storage::stable_save((state,)).unwrap();
+    // The audit log lives in its own stable memory, so only the rest of the state is copied.
+    let memory = MEMORY_MANAGER.with(|m| m.borrow().get(UPGRADE_STATE_MEMORY_ID));
+    StableCell::init(memory, Vec::new()).set(Encode!(&state).unwrap());
 }
 
 #[post_upgrade]
 fn post_upgrade() {
-    let (state,): (StableState,) = ic_cdk::storage::stable_restore().unwrap();
+    // Older versions wrote the whole state with `stable_save`. It has to be read before the memory
+    // manager claims stable memory.
+    let state = if uses_memory_manager() {
+        let memory = MEMORY_MANAGER.with(|m| m.borrow().get(UPGRADE_STATE_MEMORY_ID));
+        Decode!(StableCell::init(memory, Vec::new()).get(), StableState).unwrap()
+    } else {
+        let (state,): (StableState,) = ic_cdk::storage::stable_restore().unwrap();
+        state
+    };
     CONFIG.with(|s| {
         *s.borrow_mut() = state.config;
     });
@@ -202,6 +919,81 @@ fn post_upgrade() {
     USER_CANISTER_ID.with(|s| {
         *s.borrow_mut() = state.user_canister_id;
     });
+    NOTIFICATION_CANISTER_ID.with(|s| {
+        *s.borrow_mut() = state.notification_canister_id;
+    });
+    MENTIONS.with(|s| {
+        *s.borrow_mut() = state.mentions.unwrap_or_default();
+    });
+    NEXT_MENTION_ID.with(|s| {
+        *s.borrow_mut() = state.next_mention_id.unwrap_or_default();
+    });
+    READ_CURSORS.with(|s| {
+        *s.borrow_mut() = state.read_cursors.unwrap_or_default();
+    });
+    REACTIONS.with(|s| {
+        *s.borrow_mut() = state.reactions.unwrap_or_default();
+    });
+    ANNOUNCEMENT.with(|s| {
+        *s.borrow_mut() = state.announcement;
+    });
+    SCHEDULED_POSTS.with(|s| {
+        *s.borrow_mut() = state.scheduled_posts.unwrap_or_default();
+    });
+    AUDIT_PSEUDONYMS.with(|s| {
+        *s.borrow_mut() = state.audit_pseudonyms.unwrap_or_default();
+    });
+    NEXT_AUDIT_PSEUDONYM.with(|s| {
+        *s.borrow_mut() = state.next_audit_pseudonym.unwrap_or(0);
+    });
+    for entry in state.audit_log.unwrap_or_default() {
+        log_legacy_audit_entry(entry);
+    }
+    PENDING_OWNERSHIP_TRANSFER.with(|s| {
+        *s.borrow_mut() = state.pending_ownership_transfer;
+    });
+    FACTORY_CANISTER_ID.with(|s| {
+        *s.borrow_mut() = state.factory_canister_id;
+    });
+    CUSTOM_ROLES.with(|s| {
+        *s.borrow_mut() = state.custom_roles.unwrap_or_default();
+    });
+    JOIN_REQUESTS.with(|s| {
+        *s.borrow_mut() = state.join_requests.unwrap_or_default();
+    });
+    JOIN_REQUEST_COOLDOWNS.with(|s| {
+        *s.borrow_mut() = state.join_request_cooldowns.unwrap_or_default();
+    });
+
+    // Timers do not survive an upgrade, so scheduled posts are armed again.
+    let scheduled: Vec<(String, Option<u64>)> = POSTS.with(|p| {
+        p.borrow()
+            .values()
+            .filter(|post| post.status == PostStatus::ScheduledGlobal)
+            .map(|post| (post.id.clone(), post.publish_at))
+            .collect()
+    });
+    for (post_id, publish_at) in scheduled {
+        schedule_publication(post_id, publish_at.unwrap_or(0));
+    }
+    // A publication cut short by an upgrade has lost its text, so it goes back to review.
+    POSTS.with(|p| {
+        for post in p.borrow_mut().values_mut() {
+            if post.status == PostStatus::Publishing {
+                post.status = PostStatus::PendingGlobal;
+            }
+        }
+    });
+    let pending_retractions: Vec<String> = POSTS.with(|p| {
+        p.borrow()
+            .values()
+            .filter(|post| post.status == PostStatus::RetractPending)
+            .map(|post| post.id.clone())
+            .collect()
+    });
+    for post_id in pending_retractions {
+        schedule_retraction(post_id);
+    }
 }
 
 // ==================================================================================================
@@ -213,7 +1005,8 @@ fn init(
     initial_config: SectorConfig,
     invite_id: Principal,
     global_feed_id: Principal,
-    user_id: Principal
+    user_id: Principal,
+    notification_id: Principal
 ) {
     let owner = initial_config.owner;
     CONFIG.with(|c| {
@@ -228,9 +1021,21 @@ fn init(
     USER_CANISTER_ID.with(|id| {
         *id.borrow_mut() = Some(user_id);
     });
+    NOTIFICATION_CANISTER_ID.with(|id| {
+        *id.borrow_mut() = Some(notification_id);
+    });
+    // Sectors are installed by the factory, which is also a controller.
+    FACTORY_CANISTER_ID.with(|id| {
+        *id.borrow_mut() = Some(caller());
+    });
 
     MEMBERS.with(|m|
-        m.borrow_mut().insert(owner, Member { principal: owner, role: SectorRole::Moderator })
+        m.borrow_mut().insert(owner, Member {
+            principal: owner,
+            role: SectorRole::Moderator,
+            custom_role: None,
+            joined_at: Some(time()),
+        })
     );
     CHANNELS.with(|c|
         c
@@ -238,10 +1043,37 @@ fn init(
             .insert("general".to_string(), Channel {
                 name: "general".to_string(),
                 messages: HashMap::new(),
+                pins: None,
             })
     );
 }
 
+// Points the sector at the notification canister. Sectors installed before notifications
+// existed have none; the factory pushes it, and the sector's controllers may also set it.
+#[update]
+fn set_notification_canister(id: Principal) -> Result<(), Error> {
+    if !ic_cdk::api::is_controller(&caller()) {
+        return Err(Error::Unauthorized("Caller is not a controller of this sector.".to_string()));
+    }
+    NOTIFICATION_CANISTER_ID.with(|n_id| {
+        *n_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
+// Records the factory that hands control over on ownership transfers. Sectors installed before
+// it was recorded have none until the factory pushes its ID.
+#[update]
+fn set_factory_canister(id: Principal) -> Result<(), Error> {
+    if !ic_cdk::api::is_controller(&caller()) {
+        return Err(Error::Unauthorized("Caller is not a controller of this sector.".to_string()));
+    }
+    FACTORY_CANISTER_ID.with(|f_id| {
+        *f_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
 // ==================================================================================================
 // === Authorization Helper Queries ===
 // ==================================================================================================
@@ -265,28 +1097,88 @@ fn is_moderator() -> Result<(), Error> {
     }
 }
 
-fn is_poster() -> Result<(), Error> {
-    match get_caller_role()? {
-        SectorRole::Moderator | SectorRole::Poster => Ok(()),
-        _ => Err(Error::Unauthorized("Action requires poster or moderator role.".to_string())),
+fn is_owner() -> Result<SectorConfig, Error> {
+    let config = CONFIG.with(|c| c.borrow().clone()).ok_or_else(||
+        Error::ConfigError("Sector not initialized.".to_string())
+    )?;
+    if config.owner != caller() {
+        return Err(Error::Unauthorized("Action requires the sector owner.".to_string()));
     }
+    Ok(config)
 }
 
-// ==================================================================================================
-// === Public Query Calls ===
-// ==================================================================================================
+fn is_user_canister() -> Result<(), Error> {
+    if Some(caller()) == USER_CANISTER_ID.with(|id| *id.borrow()) {
+        Ok(())
+    } else {
+        Err(Error::Unauthorized("Caller is not the user canister.".to_string()))
+    }
+}
 
-#[query]
-fn get_my_details() -> Result<SectorDetails, Error> {
-    let my_role = get_caller_role()?;
-    let config = CONFIG.with(|c|
-        c
+fn permissions_of(member: &Member) -> u32 {
+    let base = match member.role {
+        SectorRole::Moderator => ALL_PERMISSIONS,
+        SectorRole::Poster => PERM_POST_TO_FEED,
+        SectorRole::Member => 0,
+    };
+    let custom = member.custom_role
+        .as_ref()
+        .and_then(|name| CUSTOM_ROLES.with(|r| r.borrow().get(name).map(|role| role.permissions)))
+        .unwrap_or(0);
+    base | custom
+}
+
+fn permission_name(permission: u32) -> &'static str {
+    match permission {
+        PERM_MANAGE_CHANNELS => "manage channels",
+        PERM_MANAGE_MEMBERS => "manage members",
+        PERM_APPROVE_GLOBAL_POSTS => "approve global posts",
+        PERM_ROTATE_KEYS => "rotate keys",
+        PERM_CREATE_INVITES => "create invites",
+        PERM_POST_TO_FEED => "post to feed",
+        PERM_PIN_MESSAGES => "pin messages",
+        LIST_MEMBERS_PERMISSIONS => "manage members or rotate keys",
+        _ => "required",
+    }
+}
+
+// Checks the caller holds `permission` through their base role or custom role, and returns
+// their base role for callers that need to tell moderators apart. When `permission` combines
+// several bits, holding any one of them is enough.
+fn require_permission(permission: u32) -> Result<SectorRole, Error> {
+    let caller = caller();
+    let member = MEMBERS.with(|m| m.borrow().get(&caller).cloned()).ok_or_else(||
+        Error::Unauthorized("Caller is not a member of this sector.".to_string())
+    )?;
+    if permissions_of(&member) & permission == 0 {
+        return Err(
+            Error::Unauthorized(
+                format!("Action requires the {} permission.", permission_name(permission))
+            )
+        );
+    }
+    Ok(member.role)
+}
+
+// ==================================================================================================
+// === Public Query Calls ===
+// ==================================================================================================
+
+#[query]
+fn get_my_details() -> Result<SectorDetails, Error> {
+    let my_role = get_caller_role()?;
+    let config = CONFIG.with(|c|
+        c
             .borrow()
             .clone()
             .ok_or_else(|| Error::ConfigError("Sector not initialized.".to_string()))
     )?;
     let crypto_state = CRYPTO_STATE.with(|cs| cs.borrow().clone());
     let channel_names = CHANNELS.with(|c| c.borrow().keys().cloned().collect());
+    let unread_total = unread_counts_for(caller())
+        .iter()
+        .map(|channel| channel.unread_count)
+        .sum();
 
     Ok(SectorDetails {
         name: config.name,
@@ -297,6 +1189,10 @@ fn get_my_details() -> Result<SectorDetails, Error> {
         channels: channel_names,
         rekey_required: crypto_state.rekey_required,
         current_key_epoch: crypto_state.current_key_epoch,
+        unread_total,
+        announcement: ANNOUNCEMENT.with(|a| a.borrow().clone()),
+        owner: config.owner,
+        pending_owner: PENDING_OWNERSHIP_TRANSFER.with(|t| t.borrow().as_ref().map(|t| t.to)),
     })
 }
 
@@ -305,26 +1201,74 @@ fn get_crypto_state() -> CryptoState {
     CRYPTO_STATE.with(|cs| cs.borrow().clone())
 }
 
+#[query(composite = true)]
+async fn get_sector_feed(
+    page: usize,
+    size: usize,
+    status_filter: Option<PostStatus>,
+    show_hidden: Option<bool>
+) -> Result<Vec<Post>, Error> {
+    // Public sector feeds stay readable by anyone; private ones only by members.
+    let is_private = CONFIG.with(|c| c.borrow().as_ref().map_or(true, |config| config.is_private));
+    if is_private {
+        get_caller_role()?;
+    }
+
+    let is_reviewer = require_permission(PERM_APPROVE_GLOBAL_POSTS).is_ok();
+    let excluded = hidden_authors_of_caller(show_hidden).await;
+    let viewer = caller();
+    Ok(
+        POSTS.with(|p| {
+            let mut posts: Vec<_> = p
+                .borrow()
+                .values()
+                .filter(|post| !excluded.contains(&post.author_principal))
+                .cloned()
+                .map(|post| redact_post_for(post, viewer, is_reviewer))
+                .filter(|post| status_filter.map_or(true, |status| post.status == status))
+                .collect();
+            posts.sort_by(|a, b| b.timestamp.cmp(&a.timestamp)); // Newest first
+            posts
+                .into_iter()
+                .skip(page * size)
+                .take(size)
+                .map(|post| with_post_reactions(post, viewer))
+                .collect()
+        })
+    )
+}
+
+// The moderator review queue: posts waiting for global feed approval, oldest first.
 #[query]
-fn get_sector_feed(page: usize, size: usize) -> Vec<Post> {
-    POSTS.with(|p| {
-        let mut posts: Vec<_> = p.borrow().values().cloned().collect();
-        posts.sort_by(|a, b| b.timestamp.cmp(&a.timestamp)); // Newest first
-        posts
+fn get_pending_global_posts() -> Result<Vec<Post>, Error> {
+    require_permission(PERM_APPROVE_GLOBAL_POSTS)?;
+    let viewer = caller();
+    let mut pending: Vec<Post> = POSTS.with(|p| {
+        p.borrow()
+            .values()
+            .filter(|post| post.status == PostStatus::PendingGlobal)
+            .cloned()
+            .map(|post| redact_post_for(post, viewer, true))
+            .collect()
+    });
+    pending.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    Ok(
+        pending
             .into_iter()
-            .skip(page * size)
-            .take(size)
+            .map(|post| with_post_reactions(post, viewer))
             .collect()
-    })
+    )
 }
 
-#[query]
-fn get_messages(
+#[query(composite = true)]
+async fn get_messages(
     channel_name: String,
     limit: usize,
-    before_id: Option<String>
+    before_id: Option<String>,
+    show_hidden: Option<bool>
 ) -> Result<Vec<Message>, Error> {
     get_caller_role()?; // Authorize: only members can poll for messages
+    let excluded = hidden_authors_of_caller(show_hidden).await;
 
     CHANNELS.with(|c| {
         let channels = c.borrow();
@@ -342,15 +1286,105 @@ fn get_messages(
             messages
                 .into_iter()
                 .filter(|msg| msg.timestamp < before_timestamp)
+                .filter(|msg| msg.thread_id.is_none()) // Replies live in their thread
+                .filter(|msg| !excluded.contains(&msg.author_principal))
                 .take(limit)
+                .map(|msg| with_message_reactions(msg, caller()))
+                .collect()
+        )
+    })
+}
+
+// Returns the thread `message_id` belongs to (it may be the root or any reply). Pass the ID of the
+// last reply seen as `cursor` to fetch the next page.
+#[query(composite = true)]
+async fn get_thread(
+    message_id: String,
+    cursor: Option<String>,
+    show_hidden: Option<bool>
+) -> Result<Thread, Error> {
+    get_caller_role()?;
+    let excluded = hidden_authors_of_caller(show_hidden).await;
+
+    CHANNELS.with(|c| {
+        let channels = c.borrow();
+        let channel = channels
+            .values()
+            .find(|channel| channel.messages.contains_key(&message_id))
+            .ok_or_else(|| Error::NotFound("Message not found.".to_string()))?;
+
+        let message = &channel.messages[&message_id];
+        let root_id = message.thread_id.clone().unwrap_or_else(|| message.id.clone());
+        let root = channel.messages
+            .get(&root_id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound("Thread root not found.".to_string()))?;
+
+        let after_timestamp = cursor
+            .and_then(|id| channel.messages.get(&id))
+            .map_or(0, |msg| msg.timestamp);
+        let mut replies: Vec<Message> = channel.messages
+            .values()
+            .filter(|msg| msg.thread_id.as_deref() == Some(root_id.as_str()))
+            .filter(|msg| msg.timestamp > after_timestamp)
+            .filter(|msg| !excluded.contains(&msg.author_principal))
+            .cloned()
+            .collect();
+        replies.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        replies.truncate(THREAD_PAGE_SIZE);
+
+        let viewer = caller();
+        Ok(Thread {
+            root: with_message_reactions(root, viewer),
+            replies: replies
+                .into_iter()
+                .map(|msg| with_message_reactions(msg, viewer))
+                .collect(),
+        })
+    })
+}
+
+// Pinned messages in a channel, oldest pin first.
+#[query]
+fn get_pins(channel_name: String) -> Result<Vec<PinnedMessage>, Error> {
+    get_caller_role()?;
+    let viewer = caller();
+    CHANNELS.with(|c| {
+        let channels = c.borrow();
+        let channel = channels
+            .get(&channel_name)
+            .ok_or_else(|| Error::NotFound("Channel not found.".to_string()))?;
+
+        Ok(
+            channel.pins
+                .iter()
+                .flatten()
+                .filter_map(|pin| {
+                    channel.messages.get(&pin.message_id).map(|msg| PinnedMessage {
+                        message: with_message_reactions(msg.clone(), viewer),
+                        pinned_by: pin.pinned_by,
+                        pinned_at: pin.pinned_at,
+                    })
+                })
                 .collect()
         )
     })
 }
 
 #[query]
-fn get_new_messages(channel_name: String, after_id: String) -> Result<Vec<Message>, Error> {
+fn get_unread_counts() -> Result<Vec<ChannelUnread>, Error> {
+    get_caller_role()?;
+    Ok(unread_counts_for(caller()))
+}
+
+#[query(composite = true)]
+async fn get_new_messages(
+    channel_name: String,
+    after_id: String,
+    show_hidden: Option<bool>
+) -> Result<Vec<Message>, Error> {
     get_caller_role()?; // Authorize: only members can poll for messages
+    let excluded = hidden_authors_of_caller(show_hidden).await;
 
     CHANNELS.with(|c| {
         let channels = c.borrow();
@@ -361,7 +1395,10 @@ fn get_new_messages(channel_name: String, after_id: String) -> Result<Vec<Messag
         let mut messages: Vec<_> = channel.messages
             .values()
             .filter(|msg| msg.id > after_id) // Filter for messages newer than the last known ID
+            .filter(|msg| msg.thread_id.is_none()) // Replies live in their thread
+            .filter(|msg| !excluded.contains(&msg.author_principal))
             .cloned()
+            .map(|msg| with_message_reactions(msg, caller()))
             .collect();
 
         // Sort by timestamp to ensure chronological order, although ID order should be the same
@@ -373,10 +1410,201 @@ fn get_new_messages(channel_name: String, after_id: String) -> Result<Vec<Messag
 
 #[query]
 fn get_members() -> Result<Vec<Principal>, Error> {
-    is_moderator()?;
+    require_permission(LIST_MEMBERS_PERMISSIONS)?;
     Ok(MEMBERS.with(|m| m.borrow().keys().cloned().collect()))
 }
 
+// A page of the member directory ordered by principal. Pass the last principal seen as the
+// cursor for the next page. Regular members may only browse it when the sector allows it.
+// Usernames come from the user canister in one batch lookup; if that fails they are left empty.
+#[query(composite = true)]
+async fn list_members(
+    cursor: Option<Principal>,
+    role_filter: Option<SectorRole>
+) -> Result<Vec<MemberListEntry>, Error> {
+    let directory_visible = CONFIG.with(|c| {
+        c.borrow()
+            .as_ref()
+            .map_or(false, |config| config.member_directory_visible.unwrap_or(false))
+    });
+    if !directory_visible {
+        require_permission(PERM_MANAGE_MEMBERS)?;
+    } else {
+        get_caller_role()?;
+    }
+
+    let mut entries: Vec<MemberListEntry> = MEMBERS.with(|m| {
+        let mut members: Vec<Member> = m
+            .borrow()
+            .values()
+            .filter(|member| role_filter.map_or(true, |role| member.role == role))
+            .filter(|member| cursor.map_or(true, |cursor| member.principal > cursor))
+            .cloned()
+            .collect();
+        members.sort_by(|a, b| a.principal.cmp(&b.principal));
+        members
+            .into_iter()
+            .take(MEMBERS_PAGE_SIZE)
+            .map(|member| MemberListEntry {
+                principal: member.principal,
+                role: member.role,
+                custom_role: member.custom_role,
+                joined_at: member.joined_at,
+                username: None,
+            })
+            .collect()
+    });
+
+    if let Some(user_canister_id) = USER_CANISTER_ID.with(|id| *id.borrow()) {
+        let principals: Vec<Principal> = entries
+            .iter()
+            .map(|entry| entry.principal)
+            .collect();
+        if
+            let Ok((usernames,)) = call::<_, (Vec<(Principal, String)>,)>(
+                user_canister_id,
+                "get_usernames",
+                (principals,)
+            ).await
+        {
+            let usernames: HashMap<Principal, String> = usernames.into_iter().collect();
+            for entry in entries.iter_mut() {
+                entry.username = usernames.get(&entry.principal).cloned();
+            }
+        }
+    }
+    Ok(entries)
+}
+
+// The audit log, newest first. Pass the last `id` seen as the cursor to page back.
+#[query]
+fn get_audit_log(cursor: Option<u64>) -> Result<Vec<AuditEntry>, Error> {
+    is_moderator()?;
+    // Pseudonyms of forgotten members no longer resolve and show as the anonymous principal.
+    let members: HashMap<u64, Principal> = AUDIT_PSEUDONYMS.with(|p| {
+        p.borrow()
+            .iter()
+            .map(|(member, pseudonym)| (*pseudonym, *member))
+            .collect()
+    });
+    let resolve = |pseudonym: u64| {
+        members.get(&pseudonym).copied().unwrap_or_else(Principal::anonymous)
+    };
+    Ok(
+        AUDIT_LOG.with(|log| {
+            let log = log.borrow();
+            let end = cursor.unwrap_or(u64::MAX).min(log.len());
+            (0..end)
+                .rev()
+                .take(AUDIT_LOG_PAGE_SIZE)
+                .filter_map(|id| {
+                    let entry = log.get(id)?;
+                    Some(AuditEntry {
+                        id,
+                        actor: resolve(entry.actor),
+                        action: entry.action,
+                        target: entry.target.map(|target| match target {
+                            LoggedAuditTarget::Member(pseudonym) => resolve(pseudonym).to_text(),
+                            LoggedAuditTarget::Item(item) => item,
+                        }),
+                        details: entry.details,
+                        timestamp: entry.timestamp,
+                    })
+                })
+                .collect()
+        })
+    )
+}
+
+// The caller's mentions, newest first. Pass the last `id` seen as the cursor to page back.
+#[query]
+fn get_my_mentions(cursor: Option<u64>) -> Result<Vec<Mention>, Error> {
+    get_caller_role()?;
+    let cursor = cursor.unwrap_or(u64::MAX);
+    Ok(
+        MENTIONS.with(|m| {
+            m.borrow()
+                .get(&caller())
+                .map(|mentions| {
+                    mentions
+                        .iter()
+                        .rev()
+                        .filter(|mention| mention.id < cursor)
+                        .take(MENTIONS_PAGE_SIZE)
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default()
+        })
+    )
+}
+
+// Everything this sector stores that was authored by `user`. Callable by the user themselves or
+// by the user canister when assembling a full data export.
+#[query]
+fn export_member_data(user: Principal) -> Result<MemberDataExport, Error> {
+    if caller() != user {
+        is_user_canister()?;
+    }
+
+    let role = MEMBERS.with(|m| m.borrow().get(&user).map(|member| format!("{:?}", member.role)));
+    let posts = POSTS.with(|p| {
+        p.borrow()
+            .values()
+            .filter(|post| post.author_principal == user)
+            .map(|post| ExportedPost {
+                id: post.id.clone(),
+                encrypted_content_markdown: post.encrypted_content_markdown.clone(),
+                timestamp: post.timestamp,
+                status: format!("{:?}", post.status),
+                global_post_id: post.global_post_id,
+            })
+            .collect()
+    });
+    let messages = CHANNELS.with(|c| {
+        c.borrow()
+            .values()
+            .flat_map(|channel| {
+                channel.messages
+                    .values()
+                    .filter(|msg| msg.author_principal == user)
+                    .map(|msg| ExportedMessage {
+                        id: msg.id.clone(),
+                        channel_name: channel.name.clone(),
+                        key_epoch_id: msg.key_epoch_id,
+                        timestamp: msg.timestamp,
+                        encrypted_content_markdown: msg.encrypted_content_markdown.clone(),
+                    })
+            })
+            .collect()
+    });
+
+    Ok(MemberDataExport { role, posts, messages })
+}
+
+// Members whose last heartbeat is within the presence window. Members who don't want to appear
+// online simply don't send heartbeats (or call `clear_presence`).
+#[query]
+fn get_online_members() -> Result<Vec<Principal>, Error> {
+    get_caller_role()?;
+    let now = time();
+    Ok(
+        PRESENCE.with(|p| {
+            p.borrow()
+                .iter()
+                .filter(|(_, last_seen)| now.saturating_sub(**last_seen) < PRESENCE_TTL_NS)
+                .map(|(principal, _)| *principal)
+                .collect()
+        })
+    )
+}
+
+// Used by the factory to keep the canister's controllers in line with the sector owner.
+#[query]
+fn get_sector_owner() -> Option<Principal> {
+    CONFIG.with(|c| c.borrow().as_ref().map(|config| config.owner))
+}
+
 #[query]
 fn get_member_role(principal: Principal) -> Option<SectorRole> {
     // This is a public query, but only returns a role if the principal is a member.
@@ -419,14 +1647,226 @@ fn join() -> Result<(), Error> {
             return Err(Error::InvalidState("Sector is at its maximum capacity.".to_string()));
         }
 
-        members.insert(caller, Member { principal: caller, role: SectorRole::Member });
+        members.insert(caller, Member {
+            principal: caller,
+            role: SectorRole::Member,
+            custom_role: None,
+            joined_at: Some(time()),
+        });
+        Ok(())
+    })?;
+
+    notify_membership_change(caller, true);
+    Ok(())
+}
+
+// ==================================================================================================
+// === Join Requests (Private Sectors) ===
+// ==================================================================================================
+
+// Asks to join a private sector that accepts requests. Moderators review the queue and decide.
+#[update]
+fn request_to_join(message: String) -> Result<(), Error> {
+    let caller = caller();
+    if caller == Principal::anonymous() {
+        return Err(Error::Unauthorized("Anonymous users cannot request to join.".to_string()));
+    }
+    let config = CONFIG.with(|c|
+        c
+            .borrow()
+            .clone()
+            .ok_or_else(|| Error::ConfigError("Sector not initialized.".to_string()))
+    )?;
+    if !config.is_private || !config.join_requests_enabled.unwrap_or(false) {
+        return Err(Error::InvalidState("This sector does not accept join requests.".to_string()));
+    }
+    if MEMBERS.with(|m| m.borrow().contains_key(&caller)) {
+        return Err(Error::AlreadyExists("Already a member.".to_string()));
+    }
+    let message = message.trim().to_string();
+    if message.chars().count() > MAX_JOIN_REQUEST_MESSAGE_LENGTH {
+        return Err(
+            Error::ValidationError(
+                format!(
+                    "Message must be at most {} characters.",
+                    MAX_JOIN_REQUEST_MESSAGE_LENGTH
+                )
+            )
+        );
+    }
+
+    let now = time();
+    let cooldown_until = JOIN_REQUEST_COOLDOWNS.with(|c| {
+        let mut cooldowns = c.borrow_mut();
+        cooldowns.retain(|_, until| *until > now);
+        cooldowns.get(&caller).copied()
+    });
+    if cooldown_until.is_some() {
+        return Err(
+            Error::InvalidState(
+                "You asked to join this sector recently. Try again later.".to_string()
+            )
+        );
+    }
+
+    JOIN_REQUESTS.with(|r| {
+        let mut requests = r.borrow_mut();
+        if requests.contains_key(&caller) {
+            return Err(Error::AlreadyExists("You already have a pending request.".to_string()));
+        }
+        if requests.len() >= MAX_JOIN_REQUESTS {
+            return Err(
+                Error::InvalidState(
+                    "This sector has too many pending requests. Try again later.".to_string()
+                )
+            );
+        }
+        requests.insert(caller, JoinRequest { requester: caller, message, requested_at: now });
+        Ok(())
+    })?;
+
+    let reviewers: Vec<Principal> = MEMBERS.with(|m| {
+        m.borrow()
+            .values()
+            .filter(|member| permissions_of(member) & PERM_MANAGE_MEMBERS != 0)
+            .map(|member| member.principal)
+            .collect()
+    });
+    notify_users(
+        reviewers,
+        NotificationKind::JoinRequest,
+        Some(caller),
+        None,
+        format!("Someone has asked to join {}.", config.name)
+    );
+    Ok(())
+}
+
+#[update]
+fn cancel_join_request() -> Result<(), Error> {
+    let caller = caller();
+    JOIN_REQUESTS.with(|r| r.borrow_mut().remove(&caller)).ok_or_else(||
+        Error::NotFound("You have no pending request.".to_string())
+    )?;
+    JOIN_REQUEST_COOLDOWNS.with(|c| {
+        c.borrow_mut().insert(caller, time() + JOIN_REQUEST_CANCELLED_COOLDOWN_NS);
+    });
+    Ok(())
+}
+
+// The review queue, oldest first.
+#[query]
+fn get_join_requests() -> Result<Vec<JoinRequest>, Error> {
+    require_permission(PERM_MANAGE_MEMBERS)?;
+    let mut requests: Vec<JoinRequest> = JOIN_REQUESTS.with(|r| {
+        r.borrow().values().cloned().collect()
+    });
+    requests.sort_by(|a, b| a.requested_at.cmp(&b.requested_at));
+    Ok(requests)
+}
+
+// Admits the requester with `role`. Only moderators may admit someone straight in as a moderator.
+#[update]
+fn approve_join_request(requester: Principal, role: SectorRole) -> Result<(), Error> {
+    let caller_role = require_permission(PERM_MANAGE_MEMBERS)?;
+    let caller = caller();
+    if role == SectorRole::Moderator && caller_role != SectorRole::Moderator {
+        return Err(Error::Unauthorized("Only moderators can admit new moderators.".to_string()));
+    }
+    let config = CONFIG.with(|c|
+        c
+            .borrow()
+            .clone()
+            .ok_or_else(|| Error::ConfigError("Sector not initialized.".to_string()))
+    )?;
+    if !JOIN_REQUESTS.with(|r| r.borrow().contains_key(&requester)) {
+        return Err(Error::NotFound("No pending request from this user.".to_string()));
+    }
+
+    MEMBERS.with(|m| {
+        let mut members = m.borrow_mut();
+        if
+            config.security_model == ChatSecurityModel::HighSecurityE2EE &&
+            members.len() >= HIGH_SECURITY_MEMBER_LIMIT
+        {
+            return Err(Error::InvalidState("Sector is at its maximum capacity.".to_string()));
+        }
+        members.entry(requester).or_insert(Member {
+            principal: requester,
+            role,
+            custom_role: None,
+            joined_at: Some(time()),
+        });
         Ok(())
-    })
+    })?;
+    JOIN_REQUESTS.with(|r| r.borrow_mut().remove(&requester));
+    record_audit(
+        caller,
+        AuditAction::JoinRequestApproved,
+        Some(AuditTarget::Member(requester)),
+        Some(format!("{:?}", role))
+    );
+
+    // The new member has no copy of the current key yet.
+    if config.security_model == ChatSecurityModel::HighSecurityE2EE {
+        require_rekey();
+    }
+
+    notify_membership_change(requester, true);
+    notify_users(
+        vec![requester],
+        NotificationKind::JoinRequest,
+        Some(caller),
+        None,
+        format!("Your request to join {} was approved.", config.name)
+    );
+    Ok(())
+}
+
+#[update]
+fn deny_join_request(requester: Principal, reason: Option<String>) -> Result<(), Error> {
+    require_permission(PERM_MANAGE_MEMBERS)?;
+    let caller = caller();
+    let reason = reason
+        .map(|reason| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty());
+    if reason.as_ref().map_or(false, |r| r.chars().count() > MAX_REJECTION_REASON_LENGTH) {
+        return Err(
+            Error::ValidationError(
+                format!("Reason must be at most {} characters.", MAX_REJECTION_REASON_LENGTH)
+            )
+        );
+    }
+    JOIN_REQUESTS.with(|r| r.borrow_mut().remove(&requester)).ok_or_else(||
+        Error::NotFound("No pending request from this user.".to_string())
+    )?;
+    JOIN_REQUEST_COOLDOWNS.with(|c| {
+        c.borrow_mut().insert(requester, time() + JOIN_REQUEST_DENIED_COOLDOWN_NS);
+    });
+    record_audit(
+        caller,
+        AuditAction::JoinRequestDenied,
+        Some(AuditTarget::Member(requester)),
+        reason.clone()
+    );
+
+    let message = match &reason {
+        Some(reason) => format!("Your request to join {} was declined: {}", sector_name(), reason),
+        None => format!("Your request to join {} was declined.", sector_name()),
+    };
+    notify_users(
+        vec![requester],
+        NotificationKind::JoinRequest,
+        Some(caller),
+        None,
+        message.chars().take(280).collect()
+    );
+    Ok(())
 }
 
 #[update]
 async fn create_invite_code() -> Result<String, Error> {
-    is_moderator()?;
+    require_permission(PERM_CREATE_INVITES)?;
     let config = CONFIG.with(|c|
         c
             .borrow()
@@ -437,6 +1877,42 @@ async fn create_invite_code() -> Result<String, Error> {
         return Err(Error::InvalidState("Cannot create invites for a public sector.".to_string()));
     }
 
+    let actor = caller();
+    let code = generate_invite_code().await?;
+    record_audit(actor, AuditAction::InviteCodeCreated, None, None);
+    Ok(code)
+}
+
+// Sends a specific user an invitation through their notification inbox. For private sectors the
+// notification carries a freshly registered invite code.
+#[update]
+async fn invite_user(target_user: Principal) -> Result<(), Error> {
+    require_permission(PERM_CREATE_INVITES)?;
+    let caller = caller();
+    let config = CONFIG.with(|c|
+        c
+            .borrow()
+            .clone()
+            .ok_or_else(|| Error::ConfigError("Sector not initialized.".to_string()))
+    )?;
+    if MEMBERS.with(|m| m.borrow().contains_key(&target_user)) {
+        return Err(Error::AlreadyExists("User is already a member.".to_string()));
+    }
+
+    let code = if config.is_private { Some(generate_invite_code().await?) } else { None };
+    record_audit(caller, AuditAction::MemberInvited, Some(AuditTarget::Member(target_user)), None);
+    notify_users(
+        vec![target_user],
+        NotificationKind::Invite,
+        Some(caller),
+        code,
+        format!("You've been invited to join {}.", config.name)
+    );
+    Ok(())
+}
+
+/// Registers a new random invite code with the invite canister.
+async fn generate_invite_code() -> Result<String, Error> {
     let invite_canister = INVITE_CANISTER_ID.with(|id|
         id.borrow().ok_or_else(|| Error::ConfigError("Invite canister not configured.".to_string()))
     )?;
@@ -470,16 +1946,168 @@ async fn create_invite_code() -> Result<String, Error> {
 fn leave() -> Result<(), Error> {
     let caller = caller();
     get_caller_role()?;
+    if CONFIG.with(|c| c.borrow().as_ref().map_or(false, |config| config.owner == caller)) {
+        return Err(
+            Error::InvalidState(
+                "The sector owner must transfer ownership before leaving.".to_string()
+            )
+        );
+    }
 
     MEMBERS.with(|m| m.borrow_mut().remove(&caller));
+    PRESENCE.with(|p| p.borrow_mut().remove(&caller));
+    MENTIONS.with(|m| m.borrow_mut().remove(&caller));
+    READ_CURSORS.with(|r| r.borrow_mut().remove(&caller));
 
     let config = CONFIG.with(|c| c.borrow().clone().unwrap());
     if config.security_model == ChatSecurityModel::HighSecurityE2EE {
-        CRYPTO_STATE.with(|cs| {
-            cs.borrow_mut().rekey_required = true;
-        });
+        require_rekey();
     }
 
+    notify_membership_change(caller, false);
+    Ok(())
+}
+
+// Called by the user canister when an account is deleted. Content written in a sector belongs
+// to the community, so it is kept but no longer attributed to the deleted principal.
+#[update]
+fn forget_member(user: Principal) -> Result<(), Error> {
+    is_user_canister()?;
+
+    let was_member = MEMBERS.with(|m| m.borrow_mut().remove(&user)).is_some();
+    JOIN_REQUESTS.with(|r| r.borrow_mut().remove(&user));
+    JOIN_REQUEST_COOLDOWNS.with(|c| c.borrow_mut().remove(&user));
+    READ_CURSORS.with(|r| r.borrow_mut().remove(&user));
+    REACTIONS.with(|r| {
+        let mut store = r.borrow_mut();
+        for reactions in store.values_mut() {
+            reactions.retain(|_, users| {
+                users.remove(&user);
+                !users.is_empty()
+            });
+        }
+        store.retain(|_, reactions| !reactions.is_empty());
+    });
+    MENTIONS.with(|m| {
+        let mut index = m.borrow_mut();
+        index.remove(&user);
+        for mention in index.values_mut().flatten() {
+            if mention.author_principal == user {
+                mention.author_principal = Principal::anonymous();
+            }
+        }
+    });
+    POSTS.with(|p| {
+        for post in p.borrow_mut().values_mut() {
+            if post.author_principal == user {
+                // A departed author can no longer consent, so pending submissions are withdrawn.
+                if
+                    matches!(
+                        post.status,
+                        PostStatus::PendingGlobal |
+                            PostStatus::ScheduledGlobal |
+                            PostStatus::Publishing
+                    )
+                {
+                    withdraw_global_submission(post);
+                }
+                post.author_principal = Principal::anonymous();
+            }
+        }
+    });
+    CHANNELS.with(|c| {
+        for channel in c.borrow_mut().values_mut() {
+            for msg in channel.messages.values_mut() {
+                if msg.author_principal == user {
+                    msg.author_principal = Principal::anonymous();
+                }
+            }
+            for pin in channel.pins.iter_mut().flatten() {
+                if pin.pinned_by == user {
+                    pin.pinned_by = Principal::anonymous();
+                }
+            }
+        }
+    });
+    ANNOUNCEMENT.with(|a| {
+        if let Some(announcement) = a.borrow_mut().as_mut() {
+            if announcement.posted_by == user {
+                announcement.posted_by = Principal::anonymous();
+            }
+        }
+    });
+    // Audit entries are kept for accountability, but their pseudonym no longer resolves to them.
+    AUDIT_PSEUDONYMS.with(|p| p.borrow_mut().remove(&user));
+
+    let is_e2ee = CONFIG.with(|c| {
+        c.borrow()
+            .as_ref()
+            .map_or(false, |config| config.security_model == ChatSecurityModel::HighSecurityE2EE)
+    });
+    if was_member && is_e2ee {
+        require_rekey();
+    }
+
+    Ok(())
+}
+
+#[update]
+fn kick_member(target_user: Principal) -> Result<(), Error> {
+    let caller_role = require_permission(PERM_MANAGE_MEMBERS)?;
+    let caller = caller();
+    let config = CONFIG.with(|c|
+        c
+            .borrow()
+            .clone()
+            .ok_or_else(|| Error::ConfigError("Sector not initialized.".to_string()))
+    )?;
+
+    if target_user == config.owner {
+        return Err(Error::InvalidState("The sector owner cannot be removed.".to_string()));
+    }
+    if target_user == caller {
+        return Err(Error::InvalidState("Use leave to remove yourself.".to_string()));
+    }
+    let target_role = MEMBERS.with(|m| m.borrow().get(&target_user).map(|member| member.role));
+    if caller_role != SectorRole::Moderator && target_role == Some(SectorRole::Moderator) {
+        return Err(Error::Unauthorized("Only moderators can remove a moderator.".to_string()));
+    }
+
+    MEMBERS.with(|m| {
+        m.borrow_mut()
+            .remove(&target_user)
+            .ok_or_else(||
+                Error::NotFound("Target user is not a member of this sector.".to_string())
+            )
+    })?;
+    MENTIONS.with(|m| m.borrow_mut().remove(&target_user));
+    READ_CURSORS.with(|r| r.borrow_mut().remove(&target_user));
+    record_audit(caller, AuditAction::MemberKicked, Some(AuditTarget::Member(target_user)), None);
+
+    if config.security_model == ChatSecurityModel::HighSecurityE2EE {
+        require_rekey();
+    }
+
+    notify_membership_change(target_user, false);
+    Ok(())
+}
+
+#[update]
+fn presence_heartbeat() -> Result<(), Error> {
+    get_caller_role()?;
+    let now = time();
+    PRESENCE.with(|p| {
+        let mut presence = p.borrow_mut();
+        // Drop expired entries so the map stays proportional to who is actually online.
+        presence.retain(|_, last_seen| now.saturating_sub(*last_seen) < PRESENCE_TTL_NS);
+        presence.insert(caller(), now);
+    });
+    Ok(())
+}
+
+#[update]
+fn clear_presence() -> Result<(), Error> {
+    PRESENCE.with(|p| p.borrow_mut().remove(&caller()));
     Ok(())
 }
 
@@ -501,7 +2129,7 @@ fn set_sector_role(target_user: Principal, new_role: SectorRole) -> Result<(), E
         return Err(Error::InvalidState("Moderators cannot change their own role.".to_string()));
     }
 
-    MEMBERS.with(|m| {
+    let previous_role = MEMBERS.with(|m| {
         let mut members = m.borrow_mut();
         let member = members
             .get_mut(&target_user)
@@ -509,11 +2137,146 @@ fn set_sector_role(target_user: Principal, new_role: SectorRole) -> Result<(), E
                 Error::NotFound("Target user is not a member of this sector.".to_string())
             )?;
 
+        let previous_role = member.role;
         member.role = new_role;
-        Ok(())
+        Ok::<SectorRole, Error>(previous_role)
+    })?;
+    record_audit(
+        caller,
+        AuditAction::RoleChanged,
+        Some(AuditTarget::Member(target_user)),
+        Some(format!("{:?} -> {:?}", previous_role, new_role))
+    );
+
+    notify_users(
+        vec![target_user],
+        NotificationKind::RoleChanged,
+        Some(caller),
+        None,
+        format!("Your role in {} is now {:?}.", config.name, new_role)
+    );
+    Ok(())
+}
+
+// The caller's effective permissions as a `PERM_*` bitset.
+#[query]
+fn get_my_permissions() -> Result<u32, Error> {
+    let caller = caller();
+    MEMBERS.with(|m| {
+        m.borrow()
+            .get(&caller)
+            .map(permissions_of)
+            .ok_or_else(||
+                Error::Unauthorized("Caller is not a member of this sector.".to_string())
+            )
     })
 }
 
+#[query]
+fn get_custom_roles() -> Result<Vec<CustomRole>, Error> {
+    get_caller_role()?;
+    let mut roles: Vec<CustomRole> = CUSTOM_ROLES.with(|r| r.borrow().values().cloned().collect());
+    roles.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(roles)
+}
+
+// Creates a custom role, or replaces the permissions of an existing one.
+#[update]
+fn set_custom_role(name: String, permissions: u32) -> Result<(), Error> {
+    is_moderator()?;
+    let name = name.trim().to_string();
+    if name.is_empty() || name.chars().count() > MAX_CUSTOM_ROLE_NAME_LENGTH {
+        return Err(
+            Error::ValidationError(
+                format!(
+                    "Role name must be between 1 and {} characters.",
+                    MAX_CUSTOM_ROLE_NAME_LENGTH
+                )
+            )
+        );
+    }
+    if permissions & !ALL_PERMISSIONS != 0 {
+        return Err(Error::ValidationError("Unknown permission bits.".to_string()));
+    }
+
+    CUSTOM_ROLES.with(|r| {
+        let mut roles = r.borrow_mut();
+        if !roles.contains_key(&name) && roles.len() >= MAX_CUSTOM_ROLES {
+            return Err(
+                Error::InvalidState(
+                    format!("A sector can have at most {} custom roles.", MAX_CUSTOM_ROLES)
+                )
+            );
+        }
+        roles.insert(name.clone(), CustomRole { name: name.clone(), permissions });
+        Ok(())
+    })?;
+    record_audit(
+        caller(),
+        AuditAction::CustomRoleUpdated,
+        Some(AuditTarget::Item(name)),
+        Some(format!("Permissions {:#b}", permissions))
+    );
+    Ok(())
+}
+
+// Deletes a custom role and takes it away from everyone who held it.
+#[update]
+fn delete_custom_role(name: String) -> Result<(), Error> {
+    is_moderator()?;
+    CUSTOM_ROLES.with(|r| r.borrow_mut().remove(&name)).ok_or_else(||
+        Error::NotFound("Custom role not found.".to_string())
+    )?;
+    MEMBERS.with(|m| {
+        for member in m.borrow_mut().values_mut() {
+            if member.custom_role.as_deref() == Some(name.as_str()) {
+                member.custom_role = None;
+            }
+        }
+    });
+    record_audit(caller(), AuditAction::CustomRoleDeleted, Some(AuditTarget::Item(name)), None);
+    Ok(())
+}
+
+// Gives a member a custom role, or removes theirs when `role_name` is None.
+#[update]
+fn assign_custom_role(target_user: Principal, role_name: Option<String>) -> Result<(), Error> {
+    is_moderator()?;
+    let caller = caller();
+    if target_user == caller {
+        return Err(Error::InvalidState("Moderators cannot change their own role.".to_string()));
+    }
+    if let Some(name) = &role_name {
+        if !CUSTOM_ROLES.with(|r| r.borrow().contains_key(name)) {
+            return Err(Error::NotFound("Custom role not found.".to_string()));
+        }
+    }
+
+    MEMBERS.with(|m| {
+        let mut members = m.borrow_mut();
+        let member = members
+            .get_mut(&target_user)
+            .ok_or_else(||
+                Error::NotFound("Target user is not a member of this sector.".to_string())
+            )?;
+        member.custom_role = role_name.clone();
+        Ok::<(), Error>(())
+    })?;
+
+    record_audit(
+        caller,
+        AuditAction::CustomRoleAssigned,
+        Some(AuditTarget::Member(target_user)),
+        role_name.clone()
+    );
+    let message = match &role_name {
+        Some(name) => format!("You've been given the {} role in {}.", name, sector_name()),
+        None => format!("Your custom role in {} has been removed.", sector_name()),
+    };
+    notify_users(vec![target_user], NotificationKind::RoleChanged, Some(caller), None, message);
+    Ok(())
+}
+
 // ==================================================================================================
 // === Sector Feed & Chat ===
 // ==================================================================================================
@@ -521,9 +2284,14 @@ fn set_sector_role(target_user: Principal, new_role: SectorRole) -> Result<(), E
 #[update]
 async fn create_post(
     encrypted_content_markdown: Vec<u8>,
-    for_global_feed: bool
+    for_global_feed: bool,
+    mentions: Vec<Principal>,
+    content_hash: Option<Vec<u8>>
 ) -> Result<String, Error> {
-    is_poster()?;
+    require_permission(PERM_POST_TO_FEED)?;
+    let author = caller();
+    let mentioned = validate_mentions(mentions, author)?;
+    validate_content_hash(&content_hash, for_global_feed)?;
 
     let rand_bytes = raw_rand().await.map_err(|e|
         Error::CallFailed(format!("Failed to get randomness for post ID: {:?}", e))
@@ -532,7 +2300,7 @@ async fn create_post(
 
     let post = Post {
         id: id.clone(),
-        author_principal: caller(),
+        author_principal: author,
         encrypted_content_markdown,
         timestamp: time(),
         status: if for_global_feed {
@@ -541,23 +2309,45 @@ async fn create_post(
             PostStatus::Private
         },
         global_post_id: None,
+        reactions: None,
+        edited_at: None,
+        rejection_reason: None,
+        content_hash,
+        author_confirmed_at: None,
+        publish_at: None,
     };
 
     POSTS.with(|p| p.borrow_mut().insert(id.clone(), post));
+    record_mentions(mentioned, &id, MentionLocation::Post, author);
     Ok(id)
 }
 
-#[derive(CandidType, Deserialize)]
-struct UserProfileResponse {
-    username: String,
+// Posts headed for the global feed must carry the author's SHA-256 of the plaintext, so that
+// the moderator-supplied decryption can be checked against what the author actually wrote.
+fn validate_content_hash(content_hash: &Option<Vec<u8>>, required: bool) -> Result<(), Error> {
+    match content_hash {
+        Some(hash) if hash.len() != CONTENT_HASH_LENGTH => {
+            Err(
+                Error::ValidationError("Content hash must be a 32-byte SHA-256 digest.".to_string())
+            )
+        }
+        None if required => {
+            Err(
+                Error::ValidationError(
+                    "Posts for the global feed must include a content hash.".to_string()
+                )
+            )
+        }
+        _ => Ok(()),
+    }
 }
 
+// The global feed resolves the author's username, tag and sector role itself.
 #[derive(CandidType, Deserialize)]
 struct GlobalFeedSubmission {
     author_principal: Principal,
-    author_username: String,
     content_markdown: String,
-    origin_sector_id: Principal,
+    content_hash: Option<Vec<u8>>, // Published so readers can check the copy against it
 }
 
 #[update]
@@ -565,15 +2355,12 @@ async fn approve_global_post(
     post_id: String,
     decrypted_content_markdown: String
 ) -> Result<(), Error> {
-    is_moderator()?;
-    let this_canister = ic_cdk::id();
+    require_permission(PERM_APPROVE_GLOBAL_POSTS)?;
+    let actor = caller();
 
     let config = CONFIG.with(|c| c.borrow().clone()).ok_or_else(||
         Error::ConfigError("Sector not initialized.".to_string())
     )?;
-    let user_canister_id = USER_CANISTER_ID.with(|id| *id.borrow()).ok_or_else(||
-        Error::ConfigError("User canister not configured.".to_string())
-    )?;
     let global_feed_canister_id = GLOBAL_FEED_CANISTER_ID.with(|id| *id.borrow()).ok_or_else(||
         Error::ConfigError("Global feed canister not configured.".to_string())
     )?;
@@ -586,7 +2373,9 @@ async fn approve_global_post(
         );
     }
 
-    let author_principal = POSTS.with(|p| {
+    // Everything up to the global feed call happens without awaiting, so the post moves out of
+    // `PendingGlobal` before anyone else (a second moderator, or the author) can act on it.
+    let (author_principal, content_hash, publish_at) = POSTS.with(|p| {
         let mut posts = p.borrow_mut();
         let post = posts
             .get_mut(&post_id)
@@ -595,51 +2384,515 @@ async fn approve_global_post(
         if post.status != PostStatus::PendingGlobal {
             return Err(Error::InvalidState("Post is not pending global approval.".to_string()));
         }
+        if post.author_confirmed_at.is_none() {
+            return Err(
+                Error::InvalidState(
+                    "The author has not confirmed this post for the global feed yet.".to_string()
+                )
+            );
+        }
 
-        Ok(post.author_principal)
+        // The author's hash is the only proof of what they wrote; without it nothing is published.
+        let content_hash = post.content_hash.clone().ok_or_else(||
+            Error::InvalidState(
+                "Post has no content hash; the author must edit it before approval.".to_string()
+            )
+        )?;
+        let digest = Sha256::digest(decrypted_content_markdown.as_bytes());
+        if digest.as_slice() != content_hash.as_slice() {
+            return Err(
+                Error::ValidationError(
+                    "Decrypted content does not match the author's content hash.".to_string()
+                )
+            );
+        }
+
+        let is_scheduled = post.publish_at.map_or(false, |at| at > time());
+        post.status = if is_scheduled {
+            PostStatus::ScheduledGlobal
+        } else {
+            PostStatus::Publishing
+        };
+        Ok((post.author_principal, content_hash, post.publish_at.filter(|_| is_scheduled)))
     })?;
 
-    // Get author username
-    let username = match
-        call::<_, (Option<UserProfileResponse>,)>(user_canister_id, "get_profile_by_principal", (
-            author_principal,
-        )).await
-    {
-        Ok((Some(profile),)) => profile.username,
-        _ => "anonymous".to_string(), // Default on error or if no profile
-    };
+    // Hold the verified text until the author's publication time; a timer sends it.
+    if let Some(publish_at) = publish_at {
+        SCHEDULED_POSTS.with(|s| {
+            s.borrow_mut().insert(post_id.clone(), decrypted_content_markdown);
+        });
+        schedule_publication(post_id.clone(), publish_at);
+        record_audit(
+            actor,
+            AuditAction::GlobalPostApproved,
+            Some(AuditTarget::Item(post_id)),
+            Some("Scheduled".to_string())
+        );
+        return Ok(());
+    }
+
+    publish_to_global_feed(
+        global_feed_canister_id,
+        &post_id,
+        author_principal,
+        decrypted_content_markdown,
+        content_hash,
+        PostStatus::PendingGlobal
+    ).await?;
+    record_audit(actor, AuditAction::GlobalPostApproved, Some(AuditTarget::Item(post_id)), None);
+    Ok(())
+}
 
-    // Submit to global feed
+// Sends a post that is in `Publishing` to the global feed. If the call fails the post goes back
+// to `fallback`. If the post was withdrawn while the call was in flight, the new global copy is
+// recorded as `RetractPending` and retracted again, with retries until that succeeds.
+async fn publish_to_global_feed(
+    global_feed_canister_id: Principal,
+    post_id: &str,
+    author_principal: Principal,
+    content_markdown: String,
+    content_hash: Vec<u8>,
+    fallback: PostStatus
+) -> Result<(), Error> {
     let submission = GlobalFeedSubmission {
         author_principal,
-        author_username: username,
-        content_markdown: decrypted_content_markdown,
-        origin_sector_id: this_canister,
+        content_markdown,
+        content_hash: Some(content_hash),
     };
 
-    let global_id = match
+    let result = match
         call::<_, (Result<u64, String>,)>(global_feed_canister_id, "submit_post_from_sector", (
             submission,
         )).await
     {
-        Ok((Ok(id),)) => id,
-        Ok((Err(e),)) => {
-            return Err(Error::CallFailed(format!("Global feed submission failed: {}", e)));
-        }
+        Ok((Ok(id),)) => Ok(id),
+        Ok((Err(e),)) => Err(Error::CallFailed(format!("Global feed submission failed: {}", e))),
         Err((code, msg)) => {
-            return Err(Error::CallFailed(format!("Canister call failed ({:?}): {}", code, msg)));
+            Err(Error::CallFailed(format!("Canister call failed ({:?}): {}", code, msg)))
         }
     };
 
-    // Update post state on success
-    POSTS.with(|p| {
+    let global_id = match result {
+        Ok(id) => id,
+        Err(e) => {
+            POSTS.with(|p| {
+                if let Some(post) = p.borrow_mut().get_mut(post_id) {
+                    if post.status == PostStatus::Publishing {
+                        post.status = fallback;
+                    }
+                }
+            });
+            return Err(e);
+        }
+    };
+
+    let still_publishing = POSTS.with(|p| {
         let mut posts = p.borrow_mut();
-        if let Some(post) = posts.get_mut(&post_id) {
+        let post = posts.get_mut(post_id)?;
+        post.global_post_id = Some(global_id);
+        if post.status == PostStatus::Publishing {
             post.status = PostStatus::ApprovedGlobal;
-            post.global_post_id = Some(global_id);
+            Some(true)
+        } else {
+            post.status = PostStatus::RetractPending;
+            Some(false)
+        }
+    });
+    if still_publishing != Some(true) {
+        // Posts cannot be deleted while publishing, so the post is normally still there.
+        if still_publishing.is_none() {
+            retract_from_global_feed(global_id).await?;
+        } else if retract_pending_copy(post_id).await.is_err() {
+            schedule_retraction(post_id.to_string());
+        }
+        return Err(
+            Error::InvalidState(
+                "The post changed while it was being published and was withdrawn.".to_string()
+            )
+        );
+    }
+    Ok(())
+}
+
+// Takes down the global copy of a `RetractPending` post and returns the post to the sector only.
+async fn retract_pending_copy(post_id: &str) -> Result<(), Error> {
+    let global_post_id = POSTS.with(|p| {
+        p.borrow()
+            .get(post_id)
+            .filter(|post| post.status == PostStatus::RetractPending)
+            .and_then(|post| post.global_post_id)
+    });
+    let Some(global_post_id) = global_post_id else {
+        return Ok(());
+    };
+    retract_from_global_feed(global_post_id).await?;
+    POSTS.with(|p| {
+        if let Some(post) = p.borrow_mut().get_mut(post_id) {
+            if post.status == PostStatus::RetractPending {
+                post.status = PostStatus::Private;
+                post.global_post_id = None;
+            }
+        }
+    });
+    Ok(())
+}
+
+// Retries a failed retraction of a `RetractPending` post until it goes through.
+fn schedule_retraction(post_id: String) {
+    ic_cdk_timers::set_timer(SCHEDULED_PUBLISH_RETRY, move || {
+        ic_cdk::spawn(async move {
+            if retract_pending_copy(&post_id).await.is_err() {
+                schedule_retraction(post_id);
+            }
+        })
+    });
+}
+
+// Arms a one-shot timer that publishes a scheduled post at `publish_at`.
+fn schedule_publication(post_id: String, publish_at: u64) {
+    let delay = Duration::from_nanos(publish_at.saturating_sub(time()));
+    ic_cdk_timers::set_timer(delay, move || ic_cdk::spawn(publish_scheduled_post(post_id)));
+}
+
+// Timer callback for a scheduled post. Does nothing if the post was cancelled, edited or
+// rescheduled since the timer was armed; a later timer covers the new time.
+async fn publish_scheduled_post(post_id: String) {
+    let Some(global_feed_canister_id) = GLOBAL_FEED_CANISTER_ID.with(|id| *id.borrow()) else {
+        return;
+    };
+    let now = time();
+    let due = POSTS.with(|p| {
+        let mut posts = p.borrow_mut();
+        let post = posts.get_mut(&post_id)?;
+        let is_due = post.publish_at.map_or(true, |at| at <= now);
+        if post.status != PostStatus::ScheduledGlobal || !is_due {
+            return None;
+        }
+        // The text was checked against this hash when the post was approved.
+        let content_hash = post.content_hash.clone()?;
+        let content = SCHEDULED_POSTS.with(|s| s.borrow_mut().remove(&post_id))?;
+        post.status = PostStatus::Publishing;
+        Some((post.author_principal, content, content_hash))
+    });
+    let Some((author_principal, content, content_hash)) = due else {
+        return;
+    };
+
+    let result = publish_to_global_feed(
+        global_feed_canister_id,
+        &post_id,
+        author_principal,
+        content.clone(),
+        content_hash,
+        PostStatus::ScheduledGlobal
+    ).await;
+    // Only a failed call leaves the post scheduled; keep the text and try again later.
+    let still_scheduled = POSTS.with(|p| {
+        p.borrow()
+            .get(&post_id)
+            .map_or(false, |post| post.status == PostStatus::ScheduledGlobal)
+    });
+    if result.is_err() && still_scheduled {
+        SCHEDULED_POSTS.with(|s| s.borrow_mut().insert(post_id.clone(), content));
+        ic_cdk_timers::set_timer(SCHEDULED_PUBLISH_RETRY, move || {
+            ic_cdk::spawn(publish_scheduled_post(post_id))
+        });
+    }
+}
+
+// Clears a pending or scheduled global submission, returning the post to the sector only.
+fn withdraw_global_submission(post: &mut Post) {
+    post.status = PostStatus::Private;
+    post.author_confirmed_at = None;
+    post.publish_at = None;
+    SCHEDULED_POSTS.with(|s| s.borrow_mut().remove(&post.id));
+}
+
+// The author signs off on the current text of a pending post, optionally choosing when it may
+// go out. Moderators cannot approve a post until this has happened.
+#[update]
+fn confirm_global_post(post_id: String, publish_at: Option<u64>) -> Result<(), Error> {
+    get_caller_role()?;
+    let caller = caller();
+    if publish_at.map_or(false, |at| at <= time()) {
+        return Err(Error::ValidationError("Publication time must be in the future.".to_string()));
+    }
+
+    POSTS.with(|p| {
+        let mut posts = p.borrow_mut();
+        let post = posts
+            .get_mut(&post_id)
+            .ok_or_else(|| Error::NotFound("Post not found.".to_string()))?;
+        if post.author_principal != caller {
+            return Err(Error::Unauthorized("Only the author can confirm a post.".to_string()));
+        }
+        if post.status != PostStatus::PendingGlobal {
+            return Err(Error::InvalidState("Post is not pending global approval.".to_string()));
+        }
+        post.author_confirmed_at = Some(time());
+        post.publish_at = publish_at;
+        Ok(())
+    })
+}
+
+// The author changes their mind before publication. The post stays in the sector.
+#[update]
+fn cancel_global_post(post_id: String) -> Result<(), Error> {
+    get_caller_role()?;
+    let caller = caller();
+    POSTS.with(|p| {
+        let mut posts = p.borrow_mut();
+        let post = posts
+            .get_mut(&post_id)
+            .ok_or_else(|| Error::NotFound("Post not found.".to_string()))?;
+        if post.author_principal != caller {
+            return Err(Error::Unauthorized("Only the author can cancel a submission.".to_string()));
+        }
+        if post.status == PostStatus::Publishing {
+            return Err(
+                Error::InvalidState("Post is already being published.".to_string())
+            );
+        }
+        if !matches!(post.status, PostStatus::PendingGlobal | PostStatus::ScheduledGlobal) {
+            return Err(
+                Error::InvalidState("Post is not waiting to be published globally.".to_string())
+            );
+        }
+        withdraw_global_submission(post);
+        Ok(())
+    })
+}
+
+// Removes a published copy from the global feed. Only the origin sector may do this, so the
+// call has to come from here rather than from the author directly.
+async fn retract_from_global_feed(global_post_id: u64) -> Result<(), Error> {
+    let global_feed_canister_id = GLOBAL_FEED_CANISTER_ID.with(|id| *id.borrow()).ok_or_else(||
+        Error::ConfigError("Global feed canister not configured.".to_string())
+    )?;
+
+    match
+        call::<_, (Result<(), String>,)>(global_feed_canister_id, "retract_post_from_sector", (
+            global_post_id,
+        )).await
+    {
+        Ok((Ok(()),)) => Ok(()),
+        Ok((Err(e),)) => Err(Error::CallFailed(format!("Global feed retraction failed: {}", e))),
+        Err((code, msg)) => {
+            Err(Error::CallFailed(format!("Canister call failed ({:?}): {}", code, msg)))
+        }
+    }
+}
+
+// Lets an author pull their post back off the global feed. The sector copy is kept. Authors who
+// have since left the sector may still do this for their own posts.
+#[update]
+async fn retract_global_post(post_id: String) -> Result<(), Error> {
+    let caller = caller();
+    // Posts of forgotten members are attributed to the anonymous principal.
+    if caller == Principal::anonymous() {
+        return Err(Error::Unauthorized("Anonymous users cannot retract posts.".to_string()));
+    }
+    let global_post_id = POSTS.with(|p| {
+        let posts = p.borrow();
+        let post = posts
+            .get(&post_id)
+            .ok_or_else(|| Error::NotFound("Post not found.".to_string()))?;
+        if post.author_principal != caller {
+            return Err(Error::Unauthorized("Only the author can retract a post.".to_string()));
+        }
+        match (post.status, post.global_post_id) {
+            (PostStatus::ApprovedGlobal | PostStatus::RetractPending, Some(id)) => Ok(id),
+            _ => Err(Error::InvalidState("Post is not published to the global feed.".to_string())),
+        }
+    })?;
+
+    retract_from_global_feed(global_post_id).await?;
+
+    POSTS.with(|p| {
+        if let Some(post) = p.borrow_mut().get_mut(&post_id) {
+            post.status = PostStatus::RetractedGlobal;
+            post.global_post_id = None;
         }
     });
+    Ok(())
+}
+
+#[update]
+fn reject_global_post(post_id: String, reason: String) -> Result<(), Error> {
+    require_permission(PERM_APPROVE_GLOBAL_POSTS)?;
+    let reason = reason.trim().to_string();
+    if reason.is_empty() || reason.chars().count() > MAX_REJECTION_REASON_LENGTH {
+        return Err(
+            Error::ValidationError(
+                format!(
+                    "Rejection reason must be between 1 and {} characters.",
+                    MAX_REJECTION_REASON_LENGTH
+                )
+            )
+        );
+    }
+
+    let author = POSTS.with(|p| {
+        let mut posts = p.borrow_mut();
+        let post = posts
+            .get_mut(&post_id)
+            .ok_or_else(|| Error::NotFound("Post not found.".to_string()))?;
+        if !matches!(post.status, PostStatus::PendingGlobal | PostStatus::ScheduledGlobal) {
+            return Err(Error::InvalidState("Post is not pending global approval.".to_string()));
+        }
+        withdraw_global_submission(post);
+        post.status = PostStatus::RejectedGlobal;
+        post.rejection_reason = Some(reason.clone());
+        Ok(post.author_principal)
+    })?;
 
+    record_audit(
+        caller(),
+        AuditAction::GlobalPostRejected,
+        Some(AuditTarget::Item(post_id.clone())),
+        Some(reason.clone())
+    );
+    notify_users(
+        vec![author],
+        NotificationKind::GlobalPostRejected,
+        Some(caller()),
+        Some(post_id),
+        format!("Your post in {} was not approved for the Global Feed: {}", sector_name(), reason)
+            .chars()
+            .take(280)
+            .collect()
+    );
+    Ok(())
+}
+
+// Authors can edit a post until it has been published to the global feed. Editing a rejected
+// post does not resubmit it (see `resubmit_global_post`), and editing a submission sends it back
+// for the author to confirm.
+#[update]
+fn edit_post(
+    post_id: String,
+    encrypted_content_markdown: Vec<u8>,
+    content_hash: Option<Vec<u8>>
+) -> Result<(), Error> {
+    get_caller_role()?;
+    let caller = caller();
+    POSTS.with(|p| {
+        let mut posts = p.borrow_mut();
+        let post = posts
+            .get_mut(&post_id)
+            .ok_or_else(|| Error::NotFound("Post not found.".to_string()))?;
+        if post.author_principal != caller {
+            return Err(Error::Unauthorized("Only the author can edit a post.".to_string()));
+        }
+        if
+            matches!(
+                post.status,
+                PostStatus::ApprovedGlobal | PostStatus::Publishing | PostStatus::RetractPending
+            )
+        {
+            return Err(
+                Error::InvalidState(
+                    "Posts published to the global feed can no longer be edited.".to_string()
+                )
+            );
+        }
+        let is_submission = matches!(
+            post.status,
+            PostStatus::PendingGlobal | PostStatus::ScheduledGlobal
+        );
+        validate_content_hash(&content_hash, is_submission)?;
+        if is_submission {
+            SCHEDULED_POSTS.with(|s| s.borrow_mut().remove(&post_id));
+            post.status = PostStatus::PendingGlobal;
+            post.author_confirmed_at = None;
+        }
+        post.encrypted_content_markdown = encrypted_content_markdown;
+        post.content_hash = content_hash;
+        post.edited_at = Some(time());
+        Ok(())
+    })
+}
+
+// Puts a rejected post back in the review queue, typically after the author has edited it. Like
+// any submission it needs a content hash and the author's confirmation before approval.
+#[update]
+fn resubmit_global_post(post_id: String) -> Result<(), Error> {
+    require_permission(PERM_POST_TO_FEED)?;
+    let caller = caller();
+    POSTS.with(|p| {
+        let mut posts = p.borrow_mut();
+        let post = posts
+            .get_mut(&post_id)
+            .ok_or_else(|| Error::NotFound("Post not found.".to_string()))?;
+        if post.author_principal != caller {
+            return Err(Error::Unauthorized("Only the author can resubmit a post.".to_string()));
+        }
+        if post.status != PostStatus::RejectedGlobal {
+            return Err(
+                Error::InvalidState("Only rejected posts can be resubmitted.".to_string())
+            );
+        }
+        validate_content_hash(&post.content_hash, true)?;
+        post.status = PostStatus::PendingGlobal;
+        post.rejection_reason = None;
+        post.author_confirmed_at = None;
+        post.publish_at = None;
+        Ok(())
+    })
+}
+
+// Authors can delete their own posts; moderators can delete any post. A post that was published
+// to the global feed is retracted from there first, and stays in place if that fails.
+#[update]
+async fn delete_post(post_id: String) -> Result<(), Error> {
+    let role = get_caller_role()?;
+    let caller = caller();
+    let (author, global_post_id) = POSTS.with(|p| {
+        let posts = p.borrow();
+        let post = posts
+            .get(&post_id)
+            .ok_or_else(|| Error::NotFound("Post not found.".to_string()))?;
+        if post.author_principal != caller && role != SectorRole::Moderator {
+            return Err(
+                Error::Unauthorized("Only the author or a moderator can delete a post.".to_string())
+            );
+        }
+        // The global copy's ID is only known once publication returns.
+        if post.status == PostStatus::Publishing {
+            return Err(
+                Error::InvalidState("Post is being published; try again shortly.".to_string())
+            );
+        }
+        let is_published = matches!(
+            post.status,
+            PostStatus::ApprovedGlobal | PostStatus::RetractPending
+        );
+        Ok((post.author_principal, post.global_post_id.filter(|_| is_published)))
+    })?;
+
+    if let Some(global_post_id) = global_post_id {
+        retract_from_global_feed(global_post_id).await?;
+    }
+
+    POSTS.with(|p| p.borrow_mut().remove(&post_id));
+    SCHEDULED_POSTS.with(|s| s.borrow_mut().remove(&post_id));
+    if author != caller {
+        record_audit(
+            caller,
+            AuditAction::PostDeleted,
+            Some(AuditTarget::Item(post_id.clone())),
+            None
+        );
+    }
+
+    REACTIONS.with(|r| r.borrow_mut().remove(&post_id));
+    MENTIONS.with(|m| {
+        for mentions in m.borrow_mut().values_mut() {
+            mentions.retain(|mention| {
+                !(mention.location == MentionLocation::Post && mention.item_id == post_id)
+            });
+        }
+    });
     Ok(())
 }
 
@@ -647,34 +2900,266 @@ async fn approve_global_post(
 async fn send_message(
     channel_name: String,
     encrypted_content: Vec<u8>,
-    key_epoch: u32
+    key_epoch: u32,
+    mentions: Vec<Principal>,
+    reply_to: Option<String>
 ) -> Result<String, Error> {
     get_caller_role()?;
+    let author = caller();
+    let mentioned = validate_mentions(mentions, author)?;
 
     let rand_bytes = raw_rand().await.map_err(|e|
         Error::CallFailed(format!("Failed to get randomness for message ID: {:?}", e))
     )?.0;
     let id = bytes_to_hex_string(&rand_bytes);
 
-    CHANNELS.with(|c| {
+    let parent_author = CHANNELS.with(|c| {
         let mut channels = c.borrow_mut();
         let channel = channels
             .get_mut(&channel_name)
             .ok_or_else(|| Error::NotFound("Channel not found.".to_string()))?;
 
+        // Replies join the parent's thread, so replying to a reply stays in the same thread.
+        let (thread_id, parent_author) = match &reply_to {
+            Some(parent_id) => {
+                let parent = channel.messages
+                    .get(parent_id)
+                    .ok_or_else(||
+                        Error::NotFound("The message being replied to was not found.".to_string())
+                    )?;
+                let root_id = parent.thread_id.clone().unwrap_or_else(|| parent.id.clone());
+                (Some(root_id), Some(parent.author_principal))
+            }
+            None => (None, None),
+        };
+
+        if let Some(root_id) = &thread_id {
+            if let Some(root) = channel.messages.get_mut(root_id) {
+                root.reply_count = Some(root.reply_count.unwrap_or(0) + 1);
+            }
+        }
+
         let message = Message {
             id: id.clone(),
             key_epoch_id: key_epoch,
-            author_principal: caller(),
+            author_principal: author,
             timestamp: time(),
             encrypted_content_markdown: encrypted_content,
+            reply_to,
+            thread_id,
+            reply_count: None,
+            reactions: None,
         };
 
         channel.messages.insert(id.clone(), message);
-        Ok(id)
+        Ok::<_, Error>(parent_author)
+    })?;
+
+    // A mention already pings the parent's author, so they are not told twice.
+    if let Some(parent_author) = parent_author {
+        if parent_author != author && !mentioned.contains(&parent_author) {
+            notify_users(
+                vec![parent_author],
+                NotificationKind::Reply,
+                Some(author),
+                Some(id.clone()),
+                format!("You got a reply in #{} in {}.", channel_name, sector_name())
+            );
+        }
+    }
+
+    record_mentions(mentioned, &id, MentionLocation::Message(channel_name), author);
+    Ok(id)
+}
+
+// Moves the caller's read cursor in a channel up to the given message. Cursors never move back,
+// so an older client marking an earlier message does not resurrect unread counts.
+#[update]
+fn mark_read(channel_name: String, cursor: String) -> Result<(), Error> {
+    get_caller_role()?;
+    let timestamp = CHANNELS.with(|c| {
+        let channels = c.borrow();
+        let channel = channels
+            .get(&channel_name)
+            .ok_or_else(|| Error::NotFound("Channel not found.".to_string()))?;
+        channel.messages
+            .get(&cursor)
+            .map(|msg| msg.timestamp)
+            .ok_or_else(|| Error::NotFound("Message not found.".to_string()))
+    })?;
+
+    READ_CURSORS.with(|r| {
+        let mut cursors = r.borrow_mut();
+        let read_at = cursors.entry(caller()).or_default().entry(channel_name).or_insert(timestamp);
+        *read_at = (*read_at).max(timestamp);
+    });
+    Ok(())
+}
+
+// Reacting twice with the same reaction is a no-op, so clients can retry safely.
+#[update]
+fn add_reaction(target: ReactionTarget, reaction: Vec<u8>) -> Result<(), Error> {
+    get_caller_role()?;
+    let caller = caller();
+    validate_reaction(&reaction)?;
+    let item_id = reaction_item_id(&target)?;
+
+    REACTIONS.with(|r| {
+        let mut store = r.borrow_mut();
+        let (already_reacted, mine) = store.get(&item_id).map_or((false, 0), |reactions| {
+            let already_reacted = reactions
+                .get(&reaction)
+                .map_or(false, |users| users.contains(&caller));
+            let mine = reactions
+                .values()
+                .filter(|users| users.contains(&caller))
+                .count();
+            (already_reacted, mine)
+        });
+        if already_reacted {
+            return Ok(());
+        }
+        if mine >= MAX_REACTIONS_PER_USER {
+            return Err(
+                Error::InvalidState(
+                    format!("You can add at most {} reactions here.", MAX_REACTIONS_PER_USER)
+                )
+            );
+        }
+        store.entry(item_id).or_default().entry(reaction).or_default().insert(caller);
+        Ok(())
+    })
+}
+
+#[update]
+fn remove_reaction(target: ReactionTarget, reaction: Vec<u8>) -> Result<(), Error> {
+    get_caller_role()?;
+    let caller = caller();
+    let item_id = reaction_item_id(&target)?;
+
+    REACTIONS.with(|r| {
+        let mut store = r.borrow_mut();
+        let Some(reactions) = store.get_mut(&item_id) else {
+            return Err(Error::NotFound("Reaction not found.".to_string()));
+        };
+        let removed = reactions.get_mut(&reaction).map_or(false, |users| users.remove(&caller));
+        if !removed {
+            return Err(Error::NotFound("Reaction not found.".to_string()));
+        }
+        reactions.retain(|_, users| !users.is_empty());
+        if reactions.is_empty() {
+            store.remove(&item_id);
+        }
+        Ok(())
     })
 }
 
+// ==================================================================================================
+// === Ownership ===
+// ==================================================================================================
+
+// Offers ownership to another member. Nothing changes until they accept; a new offer replaces
+// any earlier one.
+#[update]
+fn transfer_ownership(new_owner: Principal) -> Result<(), Error> {
+    let config = is_owner()?;
+    let caller = caller();
+    if new_owner == caller {
+        return Err(Error::InvalidState("You already own this sector.".to_string()));
+    }
+    if !MEMBERS.with(|m| m.borrow().contains_key(&new_owner)) {
+        return Err(Error::NotFound("The new owner must be a member of this sector.".to_string()));
+    }
+
+    PENDING_OWNERSHIP_TRANSFER.with(|t| {
+        *t.borrow_mut() = Some(OwnershipTransfer { to: new_owner, initiated_at: time() });
+    });
+    record_audit(
+        caller,
+        AuditAction::OwnershipTransferStarted,
+        Some(AuditTarget::Member(new_owner)),
+        None
+    );
+    notify_users(
+        vec![new_owner],
+        NotificationKind::RoleChanged,
+        Some(caller),
+        None,
+        format!("You've been offered ownership of {}.", config.name)
+    );
+    Ok(())
+}
+
+#[update]
+fn cancel_ownership_transfer() -> Result<(), Error> {
+    is_owner()?;
+    let transfer = PENDING_OWNERSHIP_TRANSFER.with(|t| t.borrow_mut().take()).ok_or_else(||
+        Error::NotFound("There is no pending ownership transfer.".to_string())
+    )?;
+    record_audit(
+        caller(),
+        AuditAction::OwnershipTransferCancelled,
+        Some(AuditTarget::Member(transfer.to)),
+        None
+    );
+    Ok(())
+}
+
+// Completes a transfer. The new owner becomes a moderator if they weren't already, the previous
+// owner stays on as a moderator, and the factory is asked to hand canister control over too.
+#[update]
+fn accept_ownership() -> Result<(), Error> {
+    let caller = caller();
+    let transfer = PENDING_OWNERSHIP_TRANSFER.with(|t| t.borrow().clone()).ok_or_else(||
+        Error::NotFound("There is no pending ownership transfer.".to_string())
+    )?;
+    if transfer.to != caller {
+        return Err(Error::Unauthorized("Ownership was not offered to you.".to_string()));
+    }
+    if !MEMBERS.with(|m| m.borrow().contains_key(&caller)) {
+        PENDING_OWNERSHIP_TRANSFER.with(|t| t.borrow_mut().take());
+        return Err(Error::InvalidState("You are no longer a member of this sector.".to_string()));
+    }
+    // Without the factory, canister control could not follow the ownership.
+    let factory_id = FACTORY_CANISTER_ID.with(|id| *id.borrow()).ok_or_else(|| {
+        Error::InvalidState(
+            "Sector has no factory to hand over control. Sync it on the factory first.".to_string()
+        )
+    })?;
+
+    let previous_owner = CONFIG.with(|c| {
+        let mut config = c.borrow_mut();
+        let config = config
+            .as_mut()
+            .ok_or_else(|| Error::ConfigError("Sector not initialized.".to_string()))?;
+        Ok::<Principal, Error>(std::mem::replace(&mut config.owner, caller))
+    })?;
+    MEMBERS.with(|m| {
+        if let Some(member) = m.borrow_mut().get_mut(&caller) {
+            member.role = SectorRole::Moderator;
+        }
+    });
+    PENDING_OWNERSHIP_TRANSFER.with(|t| t.borrow_mut().take());
+    record_audit(
+        caller,
+        AuditAction::OwnershipTransferred,
+        Some(AuditTarget::Member(previous_owner)),
+        None
+    );
+
+    // Fire-and-forget: if this fails, anyone involved can retry `sync_sector_controllers` on the
+    // factory directly, which reads the owner back from this canister.
+    let _ = ic_cdk::notify(factory_id, "sync_sector_controllers", (ic_cdk::id(),));
+    notify_users(
+        vec![previous_owner],
+        NotificationKind::RoleChanged,
+        Some(caller),
+        None,
+        format!("Ownership of {} has been transferred.", sector_name())
+    );
+    Ok(())
+}
+
 // ==================================================================================================
 // === Sector Management (Moderator Only) ===
 // ==================================================================================================
@@ -689,17 +3174,25 @@ fn update_sector_config(update_data: SectorConfigUpdate) -> Result<(), Error> {
             config.name = update_data.name;
             config.description = update_data.description;
             config.abbreviation = update_data.abbreviation;
+            if let Some(visible) = update_data.member_directory_visible {
+                config.member_directory_visible = Some(visible);
+            }
+            if let Some(enabled) = update_data.join_requests_enabled {
+                config.join_requests_enabled = Some(enabled);
+            }
             Ok(())
         } else {
             Err(Error::ConfigError("Sector configuration not found.".to_string()))
         }
-    })
+    })?;
+    record_audit(caller(), AuditAction::ConfigUpdated, None, None);
+    Ok(())
 }
 
 
 #[update]
 fn create_channel(channel_name: String) -> Result<(), Error> {
-    is_moderator()?;
+    require_permission(PERM_MANAGE_CHANNELS)?;
 
     CHANNELS.with(|c| {
         let mut channels = c.borrow_mut();
@@ -708,16 +3201,145 @@ fn create_channel(channel_name: String) -> Result<(), Error> {
         }
 
         channels.insert(channel_name.clone(), Channel {
-            name: channel_name,
+            name: channel_name.clone(),
             messages: HashMap::new(),
+            pins: None,
         });
         Ok(())
-    })
+    })?;
+    record_audit(
+        caller(),
+        AuditAction::ChannelCreated,
+        Some(AuditTarget::Item(channel_name)),
+        None
+    );
+    Ok(())
 }
 
 #[update]
-fn rotate_sector_key(key_batch: Vec<(Principal, Vec<u8>)>) -> Result<(), Error> {
+fn pin_message(channel_name: String, message_id: String) -> Result<(), Error> {
+    require_permission(PERM_PIN_MESSAGES)?;
+    CHANNELS.with(|c| {
+        let mut channels = c.borrow_mut();
+        let channel = channels
+            .get_mut(&channel_name)
+            .ok_or_else(|| Error::NotFound("Channel not found.".to_string()))?;
+        if !channel.messages.contains_key(&message_id) {
+            return Err(Error::NotFound("Message not found.".to_string()));
+        }
+
+        let pins = channel.pins.get_or_insert_with(Vec::new);
+        if pins.iter().any(|pin| pin.message_id == message_id) {
+            return Err(Error::AlreadyExists("Message is already pinned.".to_string()));
+        }
+        if pins.len() >= MAX_PINS_PER_CHANNEL {
+            return Err(
+                Error::InvalidState(
+                    format!(
+                        "A channel can have at most {} pins. Unpin one first.",
+                        MAX_PINS_PER_CHANNEL
+                    )
+                )
+            );
+        }
+        pins.push(Pin { message_id: message_id.clone(), pinned_by: caller(), pinned_at: time() });
+        Ok(())
+    })?;
+    record_audit(
+        caller(),
+        AuditAction::MessagePinned,
+        Some(AuditTarget::Item(channel_name)),
+        Some(format!("Message {}", message_id))
+    );
+    Ok(())
+}
+
+#[update]
+fn unpin_message(channel_name: String, message_id: String) -> Result<(), Error> {
+    require_permission(PERM_PIN_MESSAGES)?;
+    CHANNELS.with(|c| {
+        let mut channels = c.borrow_mut();
+        let channel = channels
+            .get_mut(&channel_name)
+            .ok_or_else(|| Error::NotFound("Channel not found.".to_string()))?;
+
+        let pins = channel.pins.get_or_insert_with(Vec::new);
+        let before = pins.len();
+        pins.retain(|pin| pin.message_id != message_id);
+        if pins.len() == before {
+            return Err(Error::NotFound("Message is not pinned.".to_string()));
+        }
+        Ok(())
+    })?;
+    record_audit(
+        caller(),
+        AuditAction::MessageUnpinned,
+        Some(AuditTarget::Item(channel_name)),
+        Some(format!("Message {}", message_id))
+    );
+    Ok(())
+}
+
+// Replaces the sector-wide announcement. In E2EE sectors `content` is encrypted with the key of
+// `key_epoch`, exactly like a chat message.
+#[update]
+fn set_announcement(content: Vec<u8>, key_epoch: u32) -> Result<(), Error> {
+    is_moderator()?;
+    if content.is_empty() || content.len() > MAX_ANNOUNCEMENT_BYTES {
+        return Err(
+            Error::ValidationError(
+                format!("Announcement must be between 1 and {} bytes.", MAX_ANNOUNCEMENT_BYTES)
+            )
+        );
+    }
+    ANNOUNCEMENT.with(|a| {
+        *a.borrow_mut() = Some(Announcement {
+            content,
+            key_epoch_id: key_epoch,
+            posted_by: caller(),
+            posted_at: time(),
+        });
+    });
+    record_audit(caller(), AuditAction::AnnouncementSet, None, None);
+    Ok(())
+}
+
+#[update]
+fn clear_announcement() -> Result<(), Error> {
     is_moderator()?;
+    ANNOUNCEMENT.with(|a| {
+        *a.borrow_mut() = None;
+    });
+    record_audit(caller(), AuditAction::AnnouncementCleared, None, None);
+    Ok(())
+}
+
+/// Active device IDs of every given user, as registered with the user canister.
+async fn registered_devices(users: Vec<Principal>) -> Result<HashSet<(Principal, u32)>, Error> {
+    let user_canister_id = USER_CANISTER_ID.with(|id| *id.borrow()).ok_or_else(||
+        Error::ConfigError("User canister not configured.".to_string())
+    )?;
+    let (devices,): (Vec<(Principal, Vec<DeviceKey>)>,) = call(
+        user_canister_id,
+        "get_device_keys_batch",
+        (users,)
+    ).await.map_err(|(code, msg)| {
+        Error::CallFailed(format!("Canister call failed ({:?}): {}", code, msg))
+    })?;
+    Ok(
+        devices
+            .into_iter()
+            .flat_map(|(user, keys)| keys.into_iter().map(move |key| (user, key.id)))
+            .collect()
+    )
+}
+
+// The batch holds the new sector key wrapped once per registered device of every member, as
+// `(member, device_id, wrapped_key)`, so each device a member uses can decrypt. Members without a
+// profile have no devices to wrap for, and devices the user canister no longer lists are allowed.
+#[update]
+async fn rotate_sector_key(key_batch: Vec<(Principal, u32, Vec<u8>)>) -> Result<(), Error> {
+    require_permission(PERM_ROTATE_KEYS)?;
     let config = CONFIG.with(|c| c.borrow().clone()).ok_or_else(||
         Error::ConfigError("Sector not initialized.".to_string())
     )?;
@@ -729,42 +3351,54 @@ fn rotate_sector_key(key_batch: Vec<(Principal, Vec<u8>)>) -> Result<(), Error>
         );
     }
 
-    let members = MEMBERS.with(|m| m.borrow().clone());
-    if key_batch.len() != members.len() {
-        return Err(
-            Error::ValidationError(
-                format!(
-                    "Key batch size ({}) does not match the current number of sector members ({}).",
-                    key_batch.len(),
-                    members.len()
-                )
-            )
-        );
-    }
-
-    let batch_principals: HashSet<Principal> = key_batch
+    let batch_devices: HashSet<(Principal, u32)> = key_batch
         .iter()
-        .map(|(p, _)| *p)
+        .map(|(p, device_id, _)| (*p, *device_id))
         .collect();
-    if batch_principals.len() != key_batch.len() {
-        return Err(Error::ValidationError("Duplicate principals found in key batch.".to_string()));
+    if batch_devices.len() != key_batch.len() {
+        return Err(Error::ValidationError("Duplicate devices found in key batch.".to_string()));
     }
 
-    let member_principals: HashSet<Principal> = members.keys().cloned().collect();
-    if batch_principals != member_principals {
+    let member_principals: HashSet<Principal> = MEMBERS.with(|m| {
+        m.borrow().keys().cloned().collect()
+    });
+    if batch_devices.iter().any(|(p, _)| !member_principals.contains(p)) {
+        return Err(
+            Error::ValidationError("Key batch contains keys for non-members.".to_string())
+        );
+    }
+
+    let registered = registered_devices(member_principals.iter().cloned().collect()).await?;
+    if !registered.is_subset(&batch_devices) {
         return Err(
             Error::ValidationError(
-                "Key batch principals do not match the exact set of current members.".to_string()
+                "Key batch must cover every registered device of every member.".to_string()
             )
         );
     }
+    // Someone may have joined or left while the device list was being fetched.
+    let current_members: HashSet<Principal> = MEMBERS.with(|m| {
+        m.borrow().keys().cloned().collect()
+    });
+    if current_members != member_principals {
+        return Err(
+            Error::InvalidState("Membership changed during key rotation; please retry.".to_string())
+        );
+    }
 
     // Key batch is valid, update the crypto state
-    CRYPTO_STATE.with(|cs| {
+    let new_epoch = CRYPTO_STATE.with(|cs| {
         let mut state = cs.borrow_mut();
         state.rekey_required = false;
         state.current_key_epoch += 1;
+        state.current_key_epoch
     });
+    record_audit(
+        caller(),
+        AuditAction::KeyRotated,
+        None,
+        Some(format!("Key epoch {}", new_epoch))
+    );
 
     Ok(())
 }
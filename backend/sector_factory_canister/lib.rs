@@ -1,16 +1,17 @@
 #![allow(warnings)] 
 
-use candid::{ CandidType, Deserialize, Encode, Principal };
+use candid::{ CandidType, Decode, Deserialize, Encode, Principal };
 use ic_cdk::api::{ caller, time };
 use ic_cdk_macros::*;
 use ic_stable_structures::memory_manager::{ MemoryId, MemoryManager, VirtualMemory };
-use ic_stable_structures::{ DefaultMemoryImpl, StableBTreeMap, Storable };
+use ic_stable_structures::{ DefaultMemoryImpl, StableBTreeMap, StableCell, Storable };
 use std::borrow::Cow;
 use std::cell::RefCell;
 use ic_stable_structures::storable::Bound;
 use ic_cdk::api::management_canister::main::{
-    create_canister, install_code, CanisterInstallMode, CanisterSettings, CreateCanisterArgument,
-    InstallCodeArgument, CanisterIdRecord,
+    canister_status, create_canister, install_code, update_settings, CanisterInstallMode,
+    CanisterSettings,
+    CreateCanisterArgument, InstallCodeArgument, CanisterIdRecord, UpdateSettingsArgument,
 };
 
 // ==================================================================================================
@@ -83,6 +84,14 @@ impl Storable for StorablePrincipal {
 
 // Memory IDs for stable structures
 const RATE_LIMIT_MAP_MEMORY_ID: MemoryId = MemoryId::new(0);
+const CREATED_SECTORS_MEMORY_ID: MemoryId = MemoryId::new(1);
+const UPGRADE_STATE_MEMORY_ID: MemoryId = MemoryId::new(2);
+
+// Memory manager layout (ic-stable-structures 0.7), needed to rebuild its header.
+const MEMORY_MANAGER_HEADER_SIZE: usize = 2080; // Magic, version, counts, reserved, memory sizes
+const MEMORY_MANAGER_MAX_BUCKETS: usize = 32768;
+const MEMORY_MANAGER_BUCKET_PAGES: u64 = 128;
+const UNALLOCATED_BUCKET: u8 = 255;
 static SECTOR_WASM_BYTES: &[u8] = include_bytes!("../../target/wasm32-unknown-unknown/release/sector_canister.wasm");
 
 thread_local! {
@@ -96,12 +105,19 @@ thread_local! {
         StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(RATE_LIMIT_MAP_MEMORY_ID)))
     );
 
+    // Every sector this factory has created: Sector ID -> Owner
+    static CREATED_SECTORS: RefCell<StableBTreeMap<StorablePrincipal, StorablePrincipal, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(CREATED_SECTORS_MEMORY_ID)))
+    );
+
     // State that needs manual saving on upgrade
     static OWNER: RefCell<Option<Principal>> = RefCell::new(None);
     static SECTOR_WASM: RefCell<Vec<u8>> = RefCell::new(SECTOR_WASM_BYTES.to_vec());
     static REGISTRY_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
     static INVITE_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
     static GLOBAL_FEED_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    static USER_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    static NOTIFICATION_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
 }
 
 // Constants
@@ -117,7 +133,9 @@ struct NonStableState {
     owner: Option<Principal>,
     registry_canister_id: Option<Principal>,
     invite_canister_id: Option<Principal>,
-    global_feed_canister_id: Option<Principal>
+    global_feed_canister_id: Option<Principal>,
+    user_canister_id: Option<Principal>,
+    notification_canister_id: Option<Principal>,
 }
 
 #[pre_upgrade]
@@ -127,13 +145,29 @@ fn pre_upgrade() {
         registry_canister_id: REGISTRY_CANISTER_ID.with(|id| *id.borrow()),
         invite_canister_id: INVITE_CANISTER_ID.with(|id| *id.borrow()),
         global_feed_canister_id: GLOBAL_FEED_CANISTER_ID.with(|id| *id.borrow()),
+        user_canister_id: USER_CANISTER_ID.with(|id| *id.borrow()),
+        notification_canister_id: NOTIFICATION_CANISTER_ID.with(|id| *id.borrow()),
     };
-    ic_cdk::storage::stable_save((state,)).unwrap();
+    // Saved next to the stable maps, never over the memory manager's header at offset 0.
+    let memory = MEMORY_MANAGER.with(|m| m.borrow().get(UPGRADE_STATE_MEMORY_ID));
+    StableCell::init(memory, Vec::new()).set(Encode!(&state).unwrap());
 }
 
 #[post_upgrade]
 fn post_upgrade() {
-    let (state,): (NonStableState,) = ic_cdk::storage::stable_restore().unwrap();
+    // Older versions wrote this state with `stable_save`. It has to be read before the memory
+    // manager claims stable memory.
+    let state = if uses_memory_manager() {
+        let memory = MEMORY_MANAGER.with(|m| m.borrow().get(UPGRADE_STATE_MEMORY_ID));
+        Decode!(StableCell::init(memory, Vec::new()).get(), NonStableState).unwrap()
+    } else {
+        let (state,): (NonStableState,) = ic_cdk::storage::stable_restore().unwrap();
+        // `stable_save` only overwrote the start of the header, so the stable maps can be
+        // recovered.
+        let saved_state_len = Encode!(&state).unwrap().len();
+        restore_memory_manager_header(&DefaultMemoryImpl::default(), saved_state_len);
+        state
+    };
     OWNER.with(|o| {
         *o.borrow_mut() = state.owner;
     });
@@ -146,6 +180,64 @@ fn post_upgrade() {
     GLOBAL_FEED_CANISTER_ID.with(|id| {
         *id.borrow_mut() = state.global_feed_canister_id;
     });
+    USER_CANISTER_ID.with(|id| {
+        *id.borrow_mut() = state.user_canister_id;
+    });
+    NOTIFICATION_CANISTER_ID.with(|id| {
+        *id.borrow_mut() = state.notification_canister_id;
+    });
+}
+
+/// Whether stable memory is laid out by the memory manager rather than a single `stable_save`.
+fn uses_memory_manager() -> bool {
+    if ic_cdk::api::stable::stable_size() == 0 {
+        return false;
+    }
+    let mut magic = [0u8; 3];
+    ic_cdk::api::stable::stable_read(0, &mut magic);
+    &magic == b"MGR"
+}
+
+// Older versions saved their heap state with `stable_save`, which wrote it over the memory
+// manager's header. When that state was shorter than the header, the bucket table behind it is
+// intact and the header can be rebuilt from it, with each memory sized to the buckets it owns.
+// Returns false, leaving memory untouched, if the table does not look like one.
+fn restore_memory_manager_header<M: ic_stable_structures::Memory>(
+    memory: &M,
+    saved_state_len: usize
+) -> bool {
+    if saved_state_len > MEMORY_MANAGER_HEADER_SIZE || memory.size() == 0 {
+        return false;
+    }
+    let mut buckets = vec![0u8; MEMORY_MANAGER_MAX_BUCKETS];
+    memory.read(MEMORY_MANAGER_HEADER_SIZE as u64, &mut buckets);
+    // Buckets are handed out in order, so the allocated ones form a prefix of the table.
+    let allocated = buckets
+        .iter()
+        .take_while(|owner| **owner != UNALLOCATED_BUCKET)
+        .count();
+    if allocated == 0 || buckets[allocated..].iter().any(|owner| *owner != UNALLOCATED_BUCKET) {
+        return false;
+    }
+    if memory.size() < 1 + (allocated as u64) * MEMORY_MANAGER_BUCKET_PAGES {
+        return false;
+    }
+
+    let mut memory_sizes = [0u64; UNALLOCATED_BUCKET as usize];
+    for owner in &buckets[..allocated] {
+        memory_sizes[*owner as usize] += MEMORY_MANAGER_BUCKET_PAGES;
+    }
+    let mut header = Vec::with_capacity(MEMORY_MANAGER_HEADER_SIZE);
+    header.extend_from_slice(b"MGR");
+    header.push(1); // Layout version
+    header.extend_from_slice(&(allocated as u16).to_le_bytes());
+    header.extend_from_slice(&(MEMORY_MANAGER_BUCKET_PAGES as u16).to_le_bytes());
+    header.extend_from_slice(&[0u8; 32]);
+    for size in memory_sizes {
+        header.extend_from_slice(&size.to_le_bytes());
+    }
+    memory.write(0, &header);
+    true
 }
 
 // ==================================================================================================
@@ -195,12 +287,40 @@ fn set_global_feed_canister(id: Principal) -> Result<(), Error> {
     Ok(())
 }
 
+#[update]
+fn set_user_canister(id: Principal) -> Result<(), Error> {
+    is_owner()?;
+    USER_CANISTER_ID.with(|user_id| {
+        *user_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
+#[update]
+fn set_notification_canister(id: Principal) -> Result<(), Error> {
+    is_owner()?;
+    NOTIFICATION_CANISTER_ID.with(|notif_id| {
+        *notif_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
+// ==================================================================================================
+// === Public Query Calls ===
+// ==================================================================================================
+
+// Lets other canisters (e.g. the user canister) check that a caller is a genuine sector.
+#[query]
+fn is_registered_sector(id: Principal) -> bool {
+    CREATED_SECTORS.with(|s| s.borrow().contains_key(&StorablePrincipal(id)))
+}
+
 // ==================================================================================================
 // === Core Public Function ===
 // ==================================================================================================
 
 #[update]
-async fn create_new_sector(config: SectorConfig) -> Result<Principal, Error> {
+async fn create_new_sector(mut config: SectorConfig) -> Result<Principal, Error> {
     let caller = caller();
     let now = time();
 
@@ -208,6 +328,8 @@ async fn create_new_sector(config: SectorConfig) -> Result<Principal, Error> {
     if caller == Principal::anonymous() {
         return Err(Error::Unauthorized);
     }
+    // The creator always owns the sector; a client-supplied owner is never trusted.
+    config.owner = caller;
     let wasm_module = SECTOR_WASM.with(|w| w.borrow().clone());
 
     // Enforce Rate Limiting
@@ -222,7 +344,8 @@ async fn create_new_sector(config: SectorConfig) -> Result<Principal, Error> {
 
     let invite_id = INVITE_CANISTER_ID.with(|id| id.borrow().clone()).ok_or_else(|| Error::ConfigError("Invite canister ID not configured in factory.".to_string()))?;
     let global_feed_id = GLOBAL_FEED_CANISTER_ID.with(|id| id.borrow().clone()).ok_or_else(|| Error::ConfigError("Global Feed canister ID not configured in factory.".to_string()))?;
-    let user_id = caller;
+    let user_id = USER_CANISTER_ID.with(|id| id.borrow().clone()).ok_or_else(|| Error::ConfigError("User canister ID not configured in factory.".to_string()))?;
+    let notification_id = NOTIFICATION_CANISTER_ID.with(|id| id.borrow().clone()).ok_or_else(|| Error::ConfigError("Notification canister ID not configured in factory.".to_string()))?;
 
     // Prepare canister settings
     let create_arg = CreateCanisterArgument { settings: Some(CanisterSettings {
@@ -236,12 +359,10 @@ async fn create_new_sector(config: SectorConfig) -> Result<Principal, Error> {
     let new_canister_id = canister_result.canister_id;
 
     // Encode ALL the arguments required by the sector's init function.
-    let install_arg = Encode!(&config, &invite_id, &global_feed_id, &user_id)
+    let install_arg = Encode!(&config, &invite_id, &global_feed_id, &user_id, &notification_id)
         .map_err(|e| Error::InstallFailed(format!("Failed to encode init arguments: {}", e)))?;
 
-
     // Install the SectorCanister code on the new instance
-    let install_arg = Encode!(&config).map_err(|e| Error::InstallFailed(format!("Failed to encode arg: {}", e)))?;
     let install_code_arg = InstallCodeArgument {
         mode: CanisterInstallMode::Install,
         canister_id: new_canister_id,
@@ -251,6 +372,11 @@ async fn create_new_sector(config: SectorConfig) -> Result<Principal, Error> {
 
     install_code(install_code_arg).await
         .map_err(|(code, msg)| Error::InstallFailed(format!("Install Failed Code {:?}: {}", code, msg)))?;
+
+    CREATED_SECTORS.with(|s| s.borrow_mut().insert(StorablePrincipal(new_canister_id), StorablePrincipal(config.owner)));
+
+    // The sector's init cannot make calls, so record the owner's membership on its behalf
+    let _ = ic_cdk::notify(user_id, "record_sector_membership", (new_canister_id, config.owner, true));
     
     // Register the new sector with the appropriate directory service
     if config.is_private {
@@ -277,5 +403,67 @@ async fn create_new_sector(config: SectorConfig) -> Result<Principal, Error> {
     Ok(new_canister_id)
 }
 
+// Hands canister control to whoever the sector currently reports as its owner, after an
+// ownership transfer. The sector calls this itself once the transfer is accepted; the owner can
+// call it again if that notification was lost. The owner is always read from the sector.
+#[update]
+async fn sync_sector_controllers(sector_id: Principal) -> Result<(), Error> {
+    let caller = caller();
+    if !CREATED_SECTORS.with(|s| s.borrow().contains_key(&StorablePrincipal(sector_id))) {
+        return Err(Error::ConfigError("Sector was not created by this factory.".to_string()));
+    }
+
+    let (owner,): (Option<Principal>,) = ic_cdk::call(sector_id, "get_sector_owner", ()).await
+        .map_err(|(code, msg)| Error::CallFailed(format!("Code {:?}: {}", code, msg)))?;
+    let owner = owner.ok_or_else(|| Error::ConfigError("Sector has no owner.".to_string()))?;
+    if caller != sector_id && caller != owner {
+        return Err(Error::Unauthorized);
+    }
+
+    update_settings(UpdateSettingsArgument {
+        canister_id: sector_id,
+        settings: CanisterSettings {
+            controllers: Some(vec![owner, ic_cdk::id()]), // Same layout as at creation
+            ..Default::default()
+        },
+    }).await
+        .map_err(|(code, msg)| Error::CallFailed(format!("Code {:?}: {}", code, msg)))?;
+
+    CREATED_SECTORS.with(|s| s.borrow_mut().insert(StorablePrincipal(sector_id), StorablePrincipal(owner)));
+    push_canister_ids(sector_id);
+    Ok(())
+}
+
+// Hands a sector the platform canister IDs it may have been installed without. Fire-and-forget;
+// the factory is a controller of every sector it created, which the sector checks.
+fn push_canister_ids(sector_id: Principal) {
+    let _ = ic_cdk::notify(sector_id, "set_factory_canister", (ic_cdk::id(),));
+    if let Some(notification_id) = NOTIFICATION_CANISTER_ID.with(|id| *id.borrow()) {
+        let _ = ic_cdk::notify(sector_id, "set_notification_canister", (notification_id,));
+    }
+}
+
+// Adopts a sector deployed before the factory kept track of what it created, so the user
+// canister accepts its membership updates. Only sectors this factory still controls qualify;
+// the owner is read from the sector itself.
+#[update]
+async fn register_existing_sector(sector_id: Principal) -> Result<(), Error> {
+    is_owner()?;
+    if CREATED_SECTORS.with(|s| s.borrow().contains_key(&StorablePrincipal(sector_id))) {
+        return Ok(());
+    }
+
+    // Only controllers may read a canister's status, so this proves the factory controls it.
+    canister_status(CanisterIdRecord { canister_id: sector_id }).await
+        .map_err(|(code, msg)| Error::CallFailed(format!("Code {:?}: {}", code, msg)))?;
+    let (owner,): (Option<Principal>,) = ic_cdk::call(sector_id, "get_sector_owner", ()).await
+        .map_err(|(code, msg)| Error::CallFailed(format!("Code {:?}: {}", code, msg)))?;
+    let owner = owner.ok_or_else(|| Error::ConfigError("Sector has no owner.".to_string()))?;
+
+    CREATED_SECTORS.with(|s| s.borrow_mut().insert(StorablePrincipal(sector_id), StorablePrincipal(owner)));
+    push_canister_ids(sector_id);
+    Ok(())
+}
+
 // Export the interface for the smart contract.
 ic_cdk::export_candid!();
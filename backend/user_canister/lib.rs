@@ -1,10 +1,11 @@
 #![allow(warnings)] 
 
-use candid::{ CandidType, Deserialize, Principal, Encode, Decode };
+use candid::{ CandidType, Deserialize, Principal, Encode, Decode, Reserved };
 use ic_cdk::api::{ caller, time };
+use ic_cdk::call;
 use ic_cdk_macros::*;
 use ic_stable_structures::memory_manager::{ MemoryId, MemoryManager, VirtualMemory };
-use ic_stable_structures::{ DefaultMemoryImpl, StableBTreeMap, Storable };
+use ic_stable_structures::{ DefaultMemoryImpl, StableBTreeMap, StableCell, Storable };
 use std::borrow::Cow;
 use std::cell::RefCell;
 use std::collections::HashSet;
@@ -35,6 +36,160 @@ pub struct Profile {
     last_seen_timestamp: u64,
     tags: HashSet<UserTag>, // Using HashSet for efficient add/remove/check
     joined_sectors: HashSet<Principal>,
+    // Optional so profiles stored before these fields existed still decode.
+    display_name: Option<String>,
+    bio: Option<String>,
+    avatar_url: Option<String>,
+    username_changed_at: Option<u64>,
+    privacy: Option<PrivacySettings>,
+}
+
+// Controls what other principals can see through the profile queries.
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct PrivacySettings {
+    hide_last_seen: bool,
+    hide_joined_sectors: bool,
+}
+
+// Coarse activity information for governance eligibility, exposed instead of exact timestamps.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ActivitySignal {
+    created_at: u64,
+    last_active_day: u64, // Start of the last UTC day with activity, in nanoseconds
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ProfileUpdate {
+    display_name: Option<String>,
+    bio: Option<String>,
+    avatar_url: Option<String>,
+}
+
+// A public identity key registered for one of the user's devices.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct DeviceKey {
+    id: u32,
+    label: String,
+    public_key: Vec<u8>,
+    added_at: u64,
+    revoked_at: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct DeviceKeyRing {
+    keys: Vec<DeviceKey>,
+    next_device_id: u32,
+}
+
+// Principals whose content the user doesn't want to see. Blocking also stops direct contact,
+// muting only hides content.
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct BlockList {
+    blocked: HashSet<Principal>,
+    muted: HashSet<Principal>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum TagAction {
+    Added,
+    Removed,
+}
+
+// A single entry in the tag history, recording who granted or revoked a tag and when.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct TagChange {
+    target: Principal,
+    tag: UserTag,
+    action: TagAction,
+    changed_by: Principal,
+    timestamp: u64,
+}
+
+// Actor Interfaces for Inter-Canister Calls
+// Note: These mirror the export types of the sector and global feed canisters.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ExportedPost {
+    id: String,
+    encrypted_content_markdown: Vec<u8>,
+    timestamp: u64,
+    status: String,
+    global_post_id: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ExportedMessage {
+    id: String,
+    channel_name: String,
+    key_epoch_id: u32,
+    timestamp: u64,
+    encrypted_content_markdown: Vec<u8>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct MemberDataExport {
+    role: Option<String>,
+    posts: Vec<ExportedPost>,
+    messages: Vec<ExportedMessage>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ExportedGlobalPost {
+    id: u64,
+    content_markdown: String,
+    timestamp: u64,
+    origin_sector_id: Option<Principal>,
+}
+
+// Mirrors the direct message canister's export types.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct DirectMessage {
+    id: u64,
+    key_epoch_id: u32,
+    author_principal: Principal,
+    timestamp: u64,
+    encrypted_content_markdown: Vec<u8>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ExportedConversation {
+    id: u64,
+    participants: Vec<Principal>,
+    is_group: bool,
+    created_at: u64,
+    messages: Vec<DirectMessage>,
+}
+
+// Mirrors the notification canister's interface (only the kinds this canister raises).
+#[derive(CandidType, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    RoleChanged,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct NotificationPayload {
+    kind: NotificationKind,
+    actor: Option<Principal>,
+    reference: Option<String>,
+    message: String,
+}
+
+// Data Export Bundle
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SectorDataExport {
+    sector_id: Principal,
+    data: Option<MemberDataExport>, // None if the sector could not be reached
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct DataExport {
+    profile: Profile,
+    device_keys: Vec<DeviceKey>,
+    tag_history: Vec<TagChange>,
+    block_list: BlockList,
+    global_posts: Vec<ExportedGlobalPost>,
+    sectors: Vec<SectorDataExport>,
+    direct_conversations: Vec<ExportedConversation>,
+    exported_at: u64,
 }
 
 // Custom Error Type
@@ -44,6 +199,7 @@ pub enum Error {
     NotFound,
     AlreadyExists(String),
     InvalidInput(String),
+    CallFailed(String),
 }
 
 // Stable Memory Setup
@@ -66,6 +222,54 @@ impl Storable for Profile {
     const BOUND: Bound = Bound::Unbounded;
 }
 
+impl Storable for TagChange {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).unwrap()
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        Encode!(&self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Storable for DeviceKeyRing {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).unwrap()
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        Encode!(&self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Storable for BlockList {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).unwrap()
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        Encode!(&self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
 impl Storable for StorablePrincipal {
     fn to_bytes(&self) -> Cow<[u8]> {
         Cow::from(self.0.as_slice())
@@ -88,6 +292,50 @@ impl Storable for StorablePrincipal {
 // Memory IDs for stable structures
 const PROFILES_MEMORY_ID: MemoryId = MemoryId::new(0);
 const USERNAMES_MEMORY_ID: MemoryId = MemoryId::new(1);
+const TAG_HISTORY_MEMORY_ID: MemoryId = MemoryId::new(2);
+const DEVICE_KEYS_MEMORY_ID: MemoryId = MemoryId::new(3);
+const BLOCK_LISTS_MEMORY_ID: MemoryId = MemoryId::new(4);
+const UPGRADE_STATE_MEMORY_ID: MemoryId = MemoryId::new(5);
+
+// Memory manager layout (ic-stable-structures 0.7), needed to rebuild its header.
+const MEMORY_MANAGER_HEADER_SIZE: usize = 2080; // Magic, version, counts, reserved, memory sizes
+const MEMORY_MANAGER_MAX_BUCKETS: usize = 32768;
+const MEMORY_MANAGER_BUCKET_PAGES: u64 = 128;
+const UNALLOCATED_BUCKET: u8 = 255;
+
+// Username & Profile Rules
+const USERNAME_MIN_LENGTH: usize = 3;
+const USERNAME_MAX_LENGTH: usize = 20;
+// Bumped whenever post_upgrade must run a one-off migration over the stable maps.
+// 1: username index keyed by normalized username.
+const SCHEMA_VERSION: u32 = 1;
+const USERNAME_CHANGE_COOLDOWN_NS: u64 = 30 * 24 * 3_600 * 1_000_000_000; // 30 days
+const RESERVED_USERNAMES: &[&str] = &[
+    "admin",
+    "administrator",
+    "anonymous",
+    "moderator",
+    "official",
+    "root",
+    "sectornet",
+    "support",
+    "system",
+];
+const DISPLAY_NAME_MAX_LENGTH: usize = 50;
+const BIO_MAX_LENGTH: usize = 500;
+const AVATAR_URL_MAX_LENGTH: usize = 512;
+const DAY_NS: u64 = 24 * 3_600 * 1_000_000_000;
+
+// Device Key Rules
+const MAX_ACTIVE_DEVICE_KEYS: usize = 10;
+const DEVICE_LABEL_MAX_LENGTH: usize = 50;
+const PUBLIC_KEY_MAX_SIZE: usize = 4096; // Bytes; comfortably fits an RSA-4096 JWK
+
+// Block List Rules
+const MAX_BLOCK_LIST_ENTRIES: usize = 1000;
+
+// Batch lookups (e.g. sector member lists)
+const USERNAME_LOOKUP_LIMIT: usize = 100;
 
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
@@ -99,14 +347,38 @@ thread_local! {
         StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(PROFILES_MEMORY_ID)))
     );
 
-    // Secondary index for unique username lookup: Username -> Principal
+    // Secondary index for unique username lookup: Normalized Username -> Principal
     static USERNAMES: RefCell<StableBTreeMap<String, StorablePrincipal, Memory>> = RefCell::new(
         StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(USERNAMES_MEMORY_ID)))
     );
 
+    // Append-only record of tag changes: Sequence Number -> TagChange
+    static TAG_HISTORY: RefCell<StableBTreeMap<u64, TagChange, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(TAG_HISTORY_MEMORY_ID)))
+    );
+
+    // Registered device keys: Principal -> DeviceKeyRing
+    static DEVICE_KEYS: RefCell<StableBTreeMap<StorablePrincipal, DeviceKeyRing, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(DEVICE_KEYS_MEMORY_ID)))
+    );
+
+    // Per-user block and mute lists: Principal -> BlockList
+    static BLOCK_LISTS: RefCell<StableBTreeMap<StorablePrincipal, BlockList, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(BLOCK_LISTS_MEMORY_ID)))
+    );
+
     // Manually-persisted state (small lists)
     static ADMINS: RefCell<HashSet<Principal>> = RefCell::new(HashSet::new());
     static OWNER: RefCell<Option<Principal>> = RefCell::new(None);
+    static FACTORY_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    static GLOBAL_FEED_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    static NOTIFICATION_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    static GOVERNANCE_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    static DIRECT_MESSAGE_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+
+    // Cache of sectors confirmed by the factory. Filled by `record_sector_membership`, since
+    // writes made during a query are discarded.
+    static VERIFIED_SECTORS: RefCell<HashSet<Principal>> = RefCell::new(HashSet::new());
 }
 
 // ==================================================================================================
@@ -117,6 +389,13 @@ thread_local! {
 struct NonStableState {
     owner: Option<Principal>,
     admins: HashSet<Principal>,
+    factory_canister_id: Option<Principal>,
+    global_feed_canister_id: Option<Principal>,
+    notification_canister_id: Option<Principal>,
+    governance_canister_id: Option<Principal>,
+    direct_message_canister_id: Option<Principal>,
+    schema_version: Option<u32>,
+    verified_sectors: Option<HashSet<Principal>>,
 }
 
 #[pre_upgrade]
@@ -124,19 +403,175 @@ fn pre_upgrade() {
     let state = NonStableState {
         owner: OWNER.with(|o| *o.borrow()),
         admins: ADMINS.with(|a| a.borrow().clone()),
+        factory_canister_id: FACTORY_CANISTER_ID.with(|id| *id.borrow()),
+        global_feed_canister_id: GLOBAL_FEED_CANISTER_ID.with(|id| *id.borrow()),
+        notification_canister_id: NOTIFICATION_CANISTER_ID.with(|id| *id.borrow()),
+        governance_canister_id: GOVERNANCE_CANISTER_ID.with(|id| *id.borrow()),
+        direct_message_canister_id: DIRECT_MESSAGE_CANISTER_ID.with(|id| *id.borrow()),
+        schema_version: Some(SCHEMA_VERSION),
+        verified_sectors: Some(VERIFIED_SECTORS.with(|v| v.borrow().clone())),
     };
-    ic_cdk::storage::stable_save((state,)).unwrap();
+    // Saved next to the stable maps, never over the memory manager's header at offset 0.
+    let memory = MEMORY_MANAGER.with(|m| m.borrow().get(UPGRADE_STATE_MEMORY_ID));
+    StableCell::init(memory, Vec::new()).set(Encode!(&state).unwrap());
 }
 
 #[post_upgrade]
 fn post_upgrade() {
-    let (state,): (NonStableState,) = ic_cdk::storage::stable_restore().unwrap();
+    // Older versions wrote this state with `stable_save`. It has to be read before the memory
+    // manager claims stable memory.
+    let state = if uses_memory_manager() {
+        let memory = MEMORY_MANAGER.with(|m| m.borrow().get(UPGRADE_STATE_MEMORY_ID));
+        Decode!(StableCell::init(memory, Vec::new()).get(), NonStableState).unwrap()
+    } else {
+        let (state,): (NonStableState,) = ic_cdk::storage::stable_restore().unwrap();
+        // `stable_save` only overwrote the start of the header, so the stable maps can be
+        // recovered and migrated below.
+        let saved_state_len = Encode!(&state).unwrap().len();
+        restore_memory_manager_header(&DefaultMemoryImpl::default(), saved_state_len);
+        state
+    };
     OWNER.with(|o| {
         *o.borrow_mut() = state.owner;
     });
     ADMINS.with(|a| {
         *a.borrow_mut() = state.admins;
     });
+    FACTORY_CANISTER_ID.with(|id| {
+        *id.borrow_mut() = state.factory_canister_id;
+    });
+    GLOBAL_FEED_CANISTER_ID.with(|id| {
+        *id.borrow_mut() = state.global_feed_canister_id;
+    });
+    NOTIFICATION_CANISTER_ID.with(|id| {
+        *id.borrow_mut() = state.notification_canister_id;
+    });
+    GOVERNANCE_CANISTER_ID.with(|id| {
+        *id.borrow_mut() = state.governance_canister_id;
+    });
+    DIRECT_MESSAGE_CANISTER_ID.with(|id| {
+        *id.borrow_mut() = state.direct_message_canister_id;
+    });
+    VERIFIED_SECTORS.with(|v| {
+        *v.borrow_mut() = state.verified_sectors.unwrap_or_default();
+    });
+
+    if state.schema_version.unwrap_or(0) < 1 {
+        migrate_username_index();
+    }
+}
+
+/// Whether stable memory is laid out by the memory manager rather than a single `stable_save`.
+fn uses_memory_manager() -> bool {
+    if ic_cdk::api::stable::stable_size() == 0 {
+        return false;
+    }
+    let mut magic = [0u8; 3];
+    ic_cdk::api::stable::stable_read(0, &mut magic);
+    &magic == b"MGR"
+}
+
+// Older versions saved their heap state with `stable_save`, which wrote it over the memory
+// manager's header. When that state was shorter than the header, the bucket table behind it is
+// intact and the header can be rebuilt from it, with each memory sized to the buckets it owns.
+// Returns false, leaving memory untouched, if the table does not look like one.
+fn restore_memory_manager_header<M: ic_stable_structures::Memory>(
+    memory: &M,
+    saved_state_len: usize
+) -> bool {
+    if saved_state_len > MEMORY_MANAGER_HEADER_SIZE || memory.size() == 0 {
+        return false;
+    }
+    let mut buckets = vec![0u8; MEMORY_MANAGER_MAX_BUCKETS];
+    memory.read(MEMORY_MANAGER_HEADER_SIZE as u64, &mut buckets);
+    // Buckets are handed out in order, so the allocated ones form a prefix of the table.
+    let allocated = buckets
+        .iter()
+        .take_while(|owner| **owner != UNALLOCATED_BUCKET)
+        .count();
+    if allocated == 0 || buckets[allocated..].iter().any(|owner| *owner != UNALLOCATED_BUCKET) {
+        return false;
+    }
+    if memory.size() < 1 + (allocated as u64) * MEMORY_MANAGER_BUCKET_PAGES {
+        return false;
+    }
+
+    let mut memory_sizes = [0u64; UNALLOCATED_BUCKET as usize];
+    for owner in &buckets[..allocated] {
+        memory_sizes[*owner as usize] += MEMORY_MANAGER_BUCKET_PAGES;
+    }
+    let mut header = Vec::with_capacity(MEMORY_MANAGER_HEADER_SIZE);
+    header.extend_from_slice(b"MGR");
+    header.push(1); // Layout version
+    header.extend_from_slice(&(allocated as u16).to_le_bytes());
+    header.extend_from_slice(&(MEMORY_MANAGER_BUCKET_PAGES as u16).to_le_bytes());
+    header.extend_from_slice(&[0u8; 32]);
+    for size in memory_sizes {
+        header.extend_from_slice(&size.to_le_bytes());
+    }
+    memory.write(0, &header);
+    true
+}
+
+// Re-keys the username index by normalized username. When two handles collide after
+// normalization the first principal keeps it and every other profile is given a suffixed
+// handle, so each profile's username maps back to its own index entry.
+fn migrate_username_index() {
+    let entries: Vec<(String, StorablePrincipal)> = USERNAMES.with(|u| {
+        u.borrow()
+            .iter()
+            .map(|entry| entry.into_pair())
+            .collect()
+    });
+    USERNAMES.with(|u| {
+        let mut usernames = u.borrow_mut();
+        for (username, principal) in entries {
+            let normalized = normalize_username(&username);
+            if normalized != username {
+                usernames.remove(&username);
+                if !usernames.contains_key(&normalized) {
+                    usernames.insert(normalized, principal);
+                }
+            }
+        }
+    });
+
+    let profiles: Vec<(StorablePrincipal, Profile)> = PROFILES.with(|p| {
+        p.borrow()
+            .iter()
+            .map(|entry| entry.into_pair())
+            .collect()
+    });
+    for (principal, mut profile) in profiles {
+        let normalized = normalize_username(&profile.username);
+        let holder = USERNAMES.with(|u| u.borrow().get(&normalized));
+        match holder {
+            Some(holder) if holder == principal => {}
+            None => {
+                USERNAMES.with(|u| u.borrow_mut().insert(normalized, principal));
+            }
+            Some(_) => {
+                let handle = disambiguated_username(&normalized);
+                USERNAMES.with(|u| u.borrow_mut().insert(handle.clone(), principal.clone()));
+                profile.username = handle;
+                PROFILES.with(|p| p.borrow_mut().insert(principal, profile));
+            }
+        }
+    }
+}
+
+// Finds a free `<base>_<n>` handle, truncating the base so the result stays a valid username.
+fn disambiguated_username(base: &str) -> String {
+    let mut suffix: u32 = 1;
+    loop {
+        let tail = format!("_{}", suffix);
+        let keep = USERNAME_MAX_LENGTH.saturating_sub(tail.len());
+        let candidate = format!("{}{}", base.chars().take(keep).collect::<String>(), tail);
+        if !USERNAMES.with(|u| u.borrow().contains_key(&candidate)) {
+            return candidate;
+        }
+        suffix += 1;
+    }
 }
 
 // ==================================================================================================
@@ -153,6 +588,61 @@ fn init(initial_owner: Principal) {
     });
 }
 
+fn is_owner() -> Result<(), Error> {
+    let caller = caller();
+    OWNER.with(|o| {
+        match *o.borrow() {
+            Some(owner) if owner == caller => Ok(()),
+            _ => Err(Error::Unauthorized),
+        }
+    })
+}
+
+#[update]
+fn set_factory_canister(id: Principal) -> Result<(), Error> {
+    is_owner()?;
+    FACTORY_CANISTER_ID.with(|f_id| {
+        *f_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
+#[update]
+fn set_global_feed_canister(id: Principal) -> Result<(), Error> {
+    is_owner()?;
+    GLOBAL_FEED_CANISTER_ID.with(|g_id| {
+        *g_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
+#[update]
+fn set_notification_canister(id: Principal) -> Result<(), Error> {
+    is_owner()?;
+    NOTIFICATION_CANISTER_ID.with(|n_id| {
+        *n_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
+#[update]
+fn set_governance_canister(id: Principal) -> Result<(), Error> {
+    is_owner()?;
+    GOVERNANCE_CANISTER_ID.with(|g_id| {
+        *g_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
+#[update]
+fn set_direct_message_canister(id: Principal) -> Result<(), Error> {
+    is_owner()?;
+    DIRECT_MESSAGE_CANISTER_ID.with(|d_id| {
+        *d_id.borrow_mut() = Some(id);
+    });
+    Ok(())
+}
+
 fn is_admin() -> Result<(), Error> {
     let caller = caller();
     if ADMINS.with(|a| a.borrow().contains(&caller)) {
@@ -162,6 +652,261 @@ fn is_admin() -> Result<(), Error> {
     }
 }
 
+// ==================================================================================================
+// === Validation Helpers ===
+// ==================================================================================================
+
+// Usernames are unique case-insensitively; the index is keyed by this normalized form.
+fn normalize_username(username: &str) -> String {
+    username.trim().to_lowercase()
+}
+
+// Drops an index entry only if it still points at `owner`, so a stale handle can never evict
+// the principal that actually holds it.
+fn release_username(
+    usernames: &mut StableBTreeMap<String, StorablePrincipal, Memory>,
+    normalized: &str,
+    owner: &StorablePrincipal
+) {
+    if usernames.get(&normalized.to_string()).as_ref() == Some(owner) {
+        usernames.remove(&normalized.to_string());
+    }
+}
+
+fn validate_username(username: &str) -> Result<(), Error> {
+    let length = username.chars().count();
+    if length < USERNAME_MIN_LENGTH || length > USERNAME_MAX_LENGTH {
+        return Err(
+            Error::InvalidInput(
+                format!(
+                    "Username must be between {} and {} characters.",
+                    USERNAME_MIN_LENGTH,
+                    USERNAME_MAX_LENGTH
+                )
+            )
+        );
+    }
+    if !username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(
+            Error::InvalidInput(
+                "Username may only contain letters, digits and underscores.".to_string()
+            )
+        );
+    }
+    if RESERVED_USERNAMES.contains(&normalize_username(username).as_str()) {
+        return Err(Error::InvalidInput("This username is reserved.".to_string()));
+    }
+    Ok(())
+}
+
+fn validate_optional_field(
+    value: &Option<String>,
+    field: &str,
+    max_length: usize
+) -> Result<(), Error> {
+    match value {
+        Some(v) if v.chars().count() > max_length => {
+            Err(
+                Error::InvalidInput(format!("{} must be at most {} characters.", field, max_length))
+            )
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validate_public_key(public_key: &[u8]) -> Result<(), Error> {
+    if public_key.is_empty() || public_key.len() > PUBLIC_KEY_MAX_SIZE {
+        return Err(
+            Error::InvalidInput(
+                format!("Public key must be between 1 and {} bytes.", PUBLIC_KEY_MAX_SIZE)
+            )
+        );
+    }
+    Ok(())
+}
+
+// Hides the fields the owner chose to keep private unless the caller is the owner.
+// Hidden timestamps read as 0 and hidden sector lists read as empty.
+fn redact_profile(mut profile: Profile) -> Profile {
+    if caller() == profile.owner {
+        return profile;
+    }
+    let privacy = profile.privacy.clone().unwrap_or_default();
+    if privacy.hide_last_seen {
+        profile.last_seen_timestamp = 0;
+    }
+    if privacy.hide_joined_sectors {
+        profile.joined_sectors.clear();
+    }
+    profile
+}
+
+// ==================================================================================================
+// === Block List Helpers ===
+// ==================================================================================================
+
+// Applies `change` to the caller's block list. Only users with a profile can keep one.
+fn update_block_list<F>(change: F) -> Result<(), Error> where F: FnOnce(&mut BlockList) -> bool {
+    let caller = StorablePrincipal(caller());
+    if !PROFILES.with(|p| p.borrow().contains_key(&caller)) {
+        return Err(Error::NotFound);
+    }
+
+    BLOCK_LISTS.with(|b| {
+        let mut block_lists = b.borrow_mut();
+        let mut block_list = block_lists.get(&caller).unwrap_or_default();
+        if !change(&mut block_list) {
+            return Err(Error::NotFound);
+        }
+        if block_list.blocked.len() + block_list.muted.len() > MAX_BLOCK_LIST_ENTRIES {
+            let reason = format!(
+                "Block and mute lists are limited to {} entries.",
+                MAX_BLOCK_LIST_ENTRIES
+            );
+            return Err(Error::InvalidInput(reason));
+        }
+        block_lists.insert(caller, block_list);
+        Ok(())
+    })
+}
+
+// ==================================================================================================
+// === Device Key Helpers ===
+// ==================================================================================================
+
+// Returns the user's key ring. Profiles created before the registry existed get a ring seeded
+// with their original `public_key` so it can be listed and revoked like any other device.
+fn get_or_seed_key_ring(profile: &Profile) -> DeviceKeyRing {
+    DEVICE_KEYS.with(|d| d.borrow().get(&StorablePrincipal(profile.owner))).unwrap_or_else(|| {
+        DeviceKeyRing {
+            keys: vec![DeviceKey {
+                id: 0,
+                label: "Primary device".to_string(),
+                public_key: profile.public_key.clone(),
+                added_at: profile.created_at,
+                revoked_at: None,
+            }],
+            next_device_id: 1,
+        }
+    })
+}
+
+fn active_keys(ring: &DeviceKeyRing) -> Vec<DeviceKey> {
+    ring.keys
+        .iter()
+        .filter(|key| key.revoked_at.is_none())
+        .cloned()
+        .collect()
+}
+
+// ==================================================================================================
+// === Sector Membership Helpers ===
+// ==================================================================================================
+
+// Asks the factory whether a principal is a sector it created, unless it is already cached.
+async fn is_legitimate_sector(sector_id: Principal) -> bool {
+    if VERIFIED_SECTORS.with(|v| v.borrow().contains(&sector_id)) {
+        return true;
+    }
+    let Some(factory_id) = FACTORY_CANISTER_ID.with(|id| *id.borrow()) else {
+        return false;
+    };
+    matches!(
+        call::<_, (bool,)>(factory_id, "is_registered_sector", (sector_id,)).await,
+        Ok((true,))
+    )
+}
+
+// ==================================================================================================
+// === Tag Helpers ===
+// ==================================================================================================
+
+fn record_tag_change(target: Principal, tag: UserTag, action: TagAction) {
+    let message = match action {
+        TagAction::Added => format!("You were given the {:?} tag.", tag),
+        TagAction::Removed => format!("Your {:?} tag was removed.", tag),
+    };
+    TAG_HISTORY.with(|h| {
+        let mut history = h.borrow_mut();
+        let sequence = history.len();
+        history.insert(sequence, TagChange {
+            target,
+            tag,
+            action,
+            changed_by: caller(),
+            timestamp: time(),
+        });
+    });
+
+    // Let the user know about the promotion or demotion. Fire-and-forget.
+    if let Some(notification_canister_id) = NOTIFICATION_CANISTER_ID.with(|id| *id.borrow()) {
+        let payload = NotificationPayload {
+            kind: NotificationKind::RoleChanged,
+            actor: Some(caller()),
+            reference: None,
+            message,
+        };
+        let _ = ic_cdk::notify(notification_canister_id, "push_notification", (
+            vec![target],
+            payload,
+        ));
+    }
+}
+
+// Grants a tag to a user with a profile. Granting `Admin` also adds them to ADMINS.
+fn grant_tag(target: Principal, tag: UserTag) -> Result<(), Error> {
+    let storable_principal = StorablePrincipal(target);
+    PROFILES.with(|p| {
+        let mut profiles_map = p.borrow_mut();
+        let mut profile = profiles_map.get(&storable_principal).ok_or(Error::NotFound)?.clone();
+
+        if !profile.tags.insert(tag.clone()) {
+            return Err(Error::AlreadyExists("User already has this tag.".to_string()));
+        }
+        profiles_map.insert(storable_principal, profile);
+        Ok(())
+    })?;
+
+    if tag == UserTag::Admin {
+        ADMINS.with(|a| a.borrow_mut().insert(target));
+    }
+    record_tag_change(target, tag, TagAction::Added);
+    Ok(())
+}
+
+// Revokes admin rights, keeping ADMINS and the profile's `Admin` tag in step.
+// The last remaining admin can never be removed, so the platform is never left without one.
+fn revoke_admin(target: Principal) -> Result<(), Error> {
+    let (is_listed, admin_count) = ADMINS.with(|a| {
+        let admins = a.borrow();
+        (admins.contains(&target), admins.len())
+    });
+    if is_listed && admin_count <= 1 {
+        return Err(Error::InvalidInput("Cannot remove the last admin.".to_string()));
+    }
+
+    let storable_principal = StorablePrincipal(target);
+    let had_tag = PROFILES.with(|p| {
+        let mut profiles_map = p.borrow_mut();
+        let Some(mut profile) = profiles_map.get(&storable_principal) else {
+            return false;
+        };
+        if !profile.tags.remove(&UserTag::Admin) {
+            return false;
+        }
+        profiles_map.insert(storable_principal, profile);
+        true
+    });
+
+    if !is_listed && !had_tag {
+        return Err(Error::NotFound);
+    }
+
+    ADMINS.with(|a| a.borrow_mut().remove(&target));
+    record_tag_change(target, UserTag::Admin, TagAction::Removed);
+    Ok(())
+}
+
 // ==================================================================================================
 // === Public Update Calls ===
 // ==================================================================================================
@@ -179,52 +924,278 @@ fn create_profile(username: String, public_key: Vec<u8>) -> Result<(), Error> {
         return Err(Error::AlreadyExists("Profile already exists for this principal.".to_string()));
     }
 
-    if USERNAMES.with(|u| u.borrow().contains_key(&username)) {
+    let username = username.trim().to_string();
+    validate_username(&username)?;
+    validate_public_key(&public_key)?;
+    let normalized = normalize_username(&username);
+
+    if USERNAMES.with(|u| u.borrow().contains_key(&normalized)) {
         return Err(Error::AlreadyExists("Username is already taken.".to_string()));
     }
 
+    let mut tags: HashSet<UserTag> = [UserTag::User].iter().cloned().collect();
+    // Principals granted admin rights before creating a profile (e.g. the owner) carry the tag too.
+    if ADMINS.with(|a| a.borrow().contains(&caller)) {
+        tags.insert(UserTag::Admin);
+    }
+
     let new_profile = Profile {
         owner: caller,
         username: username.clone(),
         public_key,
         created_at: now,
         last_seen_timestamp: now,
-        tags: [UserTag::User].iter().cloned().collect(),
+        tags,
         joined_sectors: HashSet::new(),
+        display_name: None,
+        bio: None,
+        avatar_url: None,
+        username_changed_at: None,
+        privacy: None,
     };
 
+    let key_ring = get_or_seed_key_ring(&new_profile);
+    DEVICE_KEYS.with(|d| d.borrow_mut().insert(StorablePrincipal(caller), key_ring));
     PROFILES.with(|p| p.borrow_mut().insert(StorablePrincipal(caller), new_profile));
-    USERNAMES.with(|u| u.borrow_mut().insert(username, StorablePrincipal(caller)));
+    USERNAMES.with(|u| u.borrow_mut().insert(normalized, StorablePrincipal(caller)));
 
     Ok(())
 }
 
 #[update]
-fn add_joined_sector(sector_id: Principal) -> Result<(), Error> {
+fn update_profile(update_data: ProfileUpdate) -> Result<(), Error> {
+    validate_optional_field(&update_data.display_name, "Display name", DISPLAY_NAME_MAX_LENGTH)?;
+    validate_optional_field(&update_data.bio, "Bio", BIO_MAX_LENGTH)?;
+    validate_optional_field(&update_data.avatar_url, "Avatar reference", AVATAR_URL_MAX_LENGTH)?;
+
     let caller = StorablePrincipal(caller());
     PROFILES.with(|p| {
         let mut profiles_map = p.borrow_mut();
-        // **FIXED**: Must clone the profile to modify it, as `get` provides an immutable reference.
         let mut profile = profiles_map.get(&caller).ok_or(Error::NotFound)?.clone();
-        profile.joined_sectors.insert(sector_id);
-        profiles_map.insert(caller, profile); // Insert the modified clone back into the map
+        profile.display_name = update_data.display_name;
+        profile.bio = update_data.bio;
+        profile.avatar_url = update_data.avatar_url;
+        profiles_map.insert(caller, profile);
         Ok(())
     })
 }
 
 #[update]
-fn remove_joined_sector(sector_id: Principal) -> Result<(), Error> {
+fn update_privacy_settings(settings: PrivacySettings) -> Result<(), Error> {
     let caller = StorablePrincipal(caller());
     PROFILES.with(|p| {
         let mut profiles_map = p.borrow_mut();
-        // **FIXED**: Must clone to modify
         let mut profile = profiles_map.get(&caller).ok_or(Error::NotFound)?.clone();
-        profile.joined_sectors.remove(&sector_id);
+        profile.privacy = Some(settings);
         profiles_map.insert(caller, profile);
         Ok(())
     })
 }
 
+#[update]
+fn block_user(user: Principal) -> Result<(), Error> {
+    if user == caller() {
+        return Err(Error::InvalidInput("You cannot block yourself.".to_string()));
+    }
+    update_block_list(|list| {
+        list.blocked.insert(user);
+        true
+    })
+}
+
+#[update]
+fn unblock_user(user: Principal) -> Result<(), Error> {
+    update_block_list(|list| list.blocked.remove(&user))
+}
+
+#[update]
+fn mute_user(user: Principal) -> Result<(), Error> {
+    if user == caller() {
+        return Err(Error::InvalidInput("You cannot mute yourself.".to_string()));
+    }
+    update_block_list(|list| {
+        list.muted.insert(user);
+        true
+    })
+}
+
+#[update]
+fn unmute_user(user: Principal) -> Result<(), Error> {
+    update_block_list(|list| list.muted.remove(&user))
+}
+
+#[update]
+fn change_username(new_username: String) -> Result<(), Error> {
+    let caller = StorablePrincipal(caller());
+    let now = time();
+
+    let new_username = new_username.trim().to_string();
+    validate_username(&new_username)?;
+    let new_normalized = normalize_username(&new_username);
+
+    let mut profile = PROFILES.with(|p| p.borrow().get(&caller)).ok_or(Error::NotFound)?;
+
+    if let Some(changed_at) = profile.username_changed_at {
+        if now.saturating_sub(changed_at) < USERNAME_CHANGE_COOLDOWN_NS {
+            return Err(
+                Error::InvalidInput("Username can only be changed once every 30 days.".to_string())
+            );
+        }
+    }
+
+    let old_normalized = normalize_username(&profile.username);
+    // A case-only change keeps the same index entry; anything else must be free.
+    if new_normalized != old_normalized {
+        if USERNAMES.with(|u| u.borrow().contains_key(&new_normalized)) {
+            return Err(Error::AlreadyExists("Username is already taken.".to_string()));
+        }
+        USERNAMES.with(|u| {
+            let mut usernames = u.borrow_mut();
+            release_username(&mut usernames, &old_normalized, &caller);
+            usernames.insert(new_normalized, caller.clone());
+        });
+    }
+
+    profile.username = new_username;
+    profile.username_changed_at = Some(now);
+    PROFILES.with(|p| p.borrow_mut().insert(caller, profile));
+
+    Ok(())
+}
+
+#[update]
+fn add_device_key(label: String, public_key: Vec<u8>) -> Result<u32, Error> {
+    let caller = caller();
+    let now = time();
+
+    validate_optional_field(&Some(label.clone()), "Device label", DEVICE_LABEL_MAX_LENGTH)?;
+    validate_public_key(&public_key)?;
+
+    let profile = PROFILES.with(|p| p.borrow().get(&StorablePrincipal(caller))).ok_or(
+        Error::NotFound
+    )?;
+    let mut key_ring = get_or_seed_key_ring(&profile);
+
+    if active_keys(&key_ring).len() >= MAX_ACTIVE_DEVICE_KEYS {
+        return Err(
+            Error::InvalidInput(
+                format!("A profile can have at most {} active device keys.", MAX_ACTIVE_DEVICE_KEYS)
+            )
+        );
+    }
+    if active_keys(&key_ring).iter().any(|key| key.public_key == public_key) {
+        return Err(Error::AlreadyExists("This public key is already registered.".to_string()));
+    }
+
+    let device_id = key_ring.next_device_id;
+    key_ring.next_device_id += 1;
+    key_ring.keys.push(DeviceKey {
+        id: device_id,
+        label,
+        public_key,
+        added_at: now,
+        revoked_at: None,
+    });
+
+    DEVICE_KEYS.with(|d| d.borrow_mut().insert(StorablePrincipal(caller), key_ring));
+    Ok(device_id)
+}
+
+#[update]
+fn revoke_device_key(device_id: u32) -> Result<(), Error> {
+    let caller = caller();
+    let now = time();
+
+    let mut profile = PROFILES.with(|p| p.borrow().get(&StorablePrincipal(caller))).ok_or(
+        Error::NotFound
+    )?;
+    let mut key_ring = get_or_seed_key_ring(&profile);
+
+    let active_count = active_keys(&key_ring).len();
+    let key = key_ring.keys
+        .iter_mut()
+        .find(|key| key.id == device_id && key.revoked_at.is_none())
+        .ok_or(Error::NotFound)?;
+
+    if active_count <= 1 {
+        return Err(Error::InvalidInput("Cannot revoke the last active device key.".to_string()));
+    }
+    key.revoked_at = Some(now);
+    let revoked_key = key.public_key.clone();
+
+    // Keep the legacy `public_key` field pointing at an active key for older clients.
+    if profile.public_key == revoked_key {
+        if let Some(newest) = active_keys(&key_ring).last() {
+            profile.public_key = newest.public_key.clone();
+            PROFILES.with(|p| p.borrow_mut().insert(StorablePrincipal(caller), profile));
+        }
+    }
+
+    DEVICE_KEYS.with(|d| d.borrow_mut().insert(StorablePrincipal(caller), key_ring));
+    Ok(())
+}
+
+// Called by sector canisters when a member joins, leaves or is removed, and by the factory
+// for the owner of a freshly created sector. Users cannot edit their own sector list.
+#[update]
+async fn record_sector_membership(
+    sector_id: Principal,
+    user: Principal,
+    is_member: bool
+) -> Result<(), Error> {
+    let caller = caller();
+    let is_factory = FACTORY_CANISTER_ID.with(|id| *id.borrow()) == Some(caller);
+    if !is_factory && (caller != sector_id || !is_legitimate_sector(caller).await) {
+        return Err(Error::Unauthorized);
+    }
+    // Every sector reports its members here, so this keeps the cache warm for the feed queries.
+    VERIFIED_SECTORS.with(|v| v.borrow_mut().insert(sector_id));
+
+    let user = StorablePrincipal(user);
+    PROFILES.with(|p| {
+        let mut profiles_map = p.borrow_mut();
+        let mut profile = profiles_map.get(&user).ok_or(Error::NotFound)?.clone();
+        if is_member {
+            profile.joined_sectors.insert(sector_id);
+        } else {
+            profile.joined_sectors.remove(&sector_id);
+        }
+        profiles_map.insert(user, profile);
+        Ok(())
+    })
+}
+
+// Re-checks every sector in the caller's list against the sector's own membership records and
+// drops the ones that no longer list the caller. Returns the corrected list.
+#[update]
+async fn reconcile_joined_sectors() -> Result<Vec<Principal>, Error> {
+    let caller = caller();
+    let profile = PROFILES.with(|p| p.borrow().get(&StorablePrincipal(caller))).ok_or(
+        Error::NotFound
+    )?;
+
+    let mut stale_sectors = Vec::new();
+    for sector_id in profile.joined_sectors.iter() {
+        // Only a definitive "not a member" answer removes a sector; failed calls keep it.
+        if let Ok((None,)) = call::<_, (Option<Reserved>,)>(*sector_id, "get_member_role", (
+            caller,
+        )).await {
+            stale_sectors.push(*sector_id);
+        }
+    }
+
+    PROFILES.with(|p| {
+        let mut profiles_map = p.borrow_mut();
+        let mut profile = profiles_map.get(&StorablePrincipal(caller)).ok_or(Error::NotFound)?;
+        for sector_id in stale_sectors.iter() {
+            profile.joined_sectors.remove(sector_id);
+        }
+        let joined_sectors = profile.joined_sectors.iter().cloned().collect();
+        profiles_map.insert(StorablePrincipal(caller), profile);
+        Ok(joined_sectors)
+    })
+}
+
 #[update]
 fn update_activity() -> Result<(), Error> {
     let caller = StorablePrincipal(caller());
@@ -238,6 +1209,151 @@ fn update_activity() -> Result<(), Error> {
     })
 }
 
+// ==================================================================================================
+// === Account Lifecycle ===
+// ==================================================================================================
+
+// Permanently deletes the caller's account. The username is freed immediately; sectors and the
+// global feed are notified to anonymize or remove what the user authored. Sector owners must
+// hand their sectors over first, or those sectors would be left without a controlling owner.
+#[update]
+async fn delete_my_account() -> Result<(), Error> {
+    let caller = caller();
+    let profile = PROFILES.with(|p| p.borrow().get(&StorablePrincipal(caller))).ok_or(
+        Error::NotFound
+    )?;
+
+    for sector_id in profile.joined_sectors.iter() {
+        // An unreachable sector is skipped rather than blocking deletion forever.
+        if
+            let Ok((Some(owner),)) = call::<_, (Option<Principal>,)>(
+                *sector_id,
+                "get_sector_owner",
+                ()
+            ).await
+        {
+            if owner == caller {
+                return Err(
+                    Error::InvalidInput(
+                        format!(
+                            "You still own sector {}. Transfer its ownership first.",
+                            sector_id
+                        )
+                    )
+                );
+            }
+        }
+    }
+    // Re-read: the profile may have changed while the sectors were being asked.
+    let profile = PROFILES.with(|p| p.borrow().get(&StorablePrincipal(caller))).ok_or(
+        Error::NotFound
+    )?;
+
+    if ADMINS.with(|a| a.borrow().contains(&caller)) {
+        revoke_admin(caller)?;
+    }
+
+    USERNAMES.with(|u| {
+        let owner = StorablePrincipal(caller);
+        release_username(&mut u.borrow_mut(), &normalize_username(&profile.username), &owner);
+    });
+    DEVICE_KEYS.with(|d| d.borrow_mut().remove(&StorablePrincipal(caller)));
+    BLOCK_LISTS.with(|b| b.borrow_mut().remove(&StorablePrincipal(caller)));
+    PROFILES.with(|p| p.borrow_mut().remove(&StorablePrincipal(caller)));
+
+    // Fire-and-forget: the account is gone even if a sector is temporarily unreachable.
+    for sector_id in profile.joined_sectors.iter() {
+        let _ = ic_cdk::notify(*sector_id, "forget_member", (caller,));
+    }
+    if let Some(global_feed_id) = GLOBAL_FEED_CANISTER_ID.with(|id| *id.borrow()) {
+        let _ = ic_cdk::notify(global_feed_id, "remove_author_content", (caller,));
+    }
+    if let Some(notification_canister_id) = NOTIFICATION_CANISTER_ID.with(|id| *id.borrow()) {
+        let _ = ic_cdk::notify(notification_canister_id, "forget_user", (caller,));
+    }
+    if let Some(direct_message_canister_id) = DIRECT_MESSAGE_CANISTER_ID.with(|id| *id.borrow()) {
+        let _ = ic_cdk::notify(direct_message_canister_id, "forget_user", (caller,));
+    }
+
+    Ok(())
+}
+
+// Collects everything the platform holds about the caller into a single Candid bundle.
+#[update]
+async fn export_my_data() -> Result<DataExport, Error> {
+    let caller = caller();
+    let profile = PROFILES.with(|p| p.borrow().get(&StorablePrincipal(caller))).ok_or(
+        Error::NotFound
+    )?;
+
+    let device_keys = get_or_seed_key_ring(&profile).keys;
+    let tag_history = TAG_HISTORY.with(|h| {
+        h.borrow()
+            .values()
+            .filter(|change| change.target == caller)
+            .collect()
+    });
+
+    let global_posts = match GLOBAL_FEED_CANISTER_ID.with(|id| *id.borrow()) {
+        Some(global_feed_id) =>
+            call::<_, (Vec<ExportedGlobalPost>,)>(global_feed_id, "get_posts_by_author", (caller,))
+                .await
+                .map(|(posts,)| posts)
+                .map_err(|(code, msg)| {
+                    let reason = format!("Failed to export global posts ({:?}): {}", code, msg);
+                    Error::CallFailed(reason)
+                })?,
+        None => Vec::new(),
+    };
+
+    let mut sectors = Vec::new();
+    for sector_id in profile.joined_sectors.iter() {
+        let data = match
+            call::<_, (Result<MemberDataExport, Reserved>,)>(*sector_id, "export_member_data", (
+                caller,
+            )).await
+        {
+            Ok((Ok(data),)) => Some(data),
+            _ => None,
+        };
+        sectors.push(SectorDataExport { sector_id: *sector_id, data });
+    }
+
+    let direct_conversations = match DIRECT_MESSAGE_CANISTER_ID.with(|id| *id.borrow()) {
+        Some(direct_message_canister_id) =>
+            match
+                call::<_, (Result<Vec<ExportedConversation>, Reserved>,)>(
+                    direct_message_canister_id,
+                    "export_for",
+                    (caller,)
+                ).await
+            {
+                Ok((Ok(conversations),)) => conversations,
+                Ok((Err(_),)) => {
+                    return Err(
+                        Error::CallFailed("Direct message export was refused.".to_string())
+                    );
+                }
+                Err((code, msg)) => {
+                    let reason = format!("Failed to export direct messages ({:?}): {}", code, msg);
+                    return Err(Error::CallFailed(reason));
+                }
+            }
+        None => Vec::new(),
+    };
+
+    Ok(DataExport {
+        profile,
+        device_keys,
+        tag_history,
+        block_list: get_my_block_list(),
+        global_posts,
+        sectors,
+        direct_conversations,
+        exported_at: time(),
+    })
+}
+
 // ==================================================================================================
 // === Public Query Calls ===
 // ==================================================================================================
@@ -249,62 +1365,185 @@ fn profile_exists(id: Principal) -> bool {
 
 #[query]
 fn get_profile_by_principal(id: Principal) -> Option<Profile> {
-    PROFILES.with(|p| p.borrow().get(&StorablePrincipal(id)))
+    PROFILES.with(|p| p.borrow().get(&StorablePrincipal(id))).map(redact_profile)
+}
+
+// Batch username lookup for member lists. Unknown principals are left out.
+#[query]
+fn get_usernames(principals: Vec<Principal>) -> Vec<(Principal, String)> {
+    PROFILES.with(|p| {
+        let profiles = p.borrow();
+        principals
+            .into_iter()
+            .take(USERNAME_LOOKUP_LIMIT)
+            .filter_map(|id| {
+                profiles.get(&StorablePrincipal(id)).map(|profile| (id, profile.username))
+            })
+            .collect()
+    })
 }
 
 #[query]
 fn get_profile_by_username(username: String) -> Option<Profile> {
     USERNAMES.with(|u| {
         u.borrow()
-            .get(&username)
+            .get(&normalize_username(&username))
             .and_then(|principal| { PROFILES.with(|p| p.borrow().get(&principal)) })
+            .map(redact_profile)
     })
 }
 
+#[query]
+fn get_my_block_list() -> BlockList {
+    BLOCK_LISTS.with(|b| b.borrow().get(&StorablePrincipal(caller()))).unwrap_or_default()
+}
+
+fn hidden_authors(user: Principal) -> Vec<Principal> {
+    let block_list = BLOCK_LISTS.with(|b| b.borrow().get(&StorablePrincipal(user)))
+        .unwrap_or_default();
+    block_list.blocked.union(&block_list.muted).cloned().collect()
+}
+
+// Everyone whose content the caller has hidden (blocked or muted).
+#[query]
+fn get_hidden_authors() -> Vec<Principal> {
+    hidden_authors(caller())
+}
+
+// Lets the global feed and sectors filter a viewer's hidden authors out of feeds on the server.
+#[query(composite = true)]
+async fn get_hidden_authors_of(viewer: Principal) -> Result<Vec<Principal>, Error> {
+    let caller = caller();
+    let is_global_feed = Some(caller) == GLOBAL_FEED_CANISTER_ID.with(|id| *id.borrow());
+    if !is_global_feed && !is_legitimate_sector(caller).await {
+        return Err(Error::Unauthorized);
+    }
+    Ok(hidden_authors(viewer))
+}
+
+// Lets the direct message canister check whether `user` has blocked `other`.
+#[query]
+fn has_blocked(user: Principal, other: Principal) -> Result<bool, Error> {
+    if Some(caller()) != DIRECT_MESSAGE_CANISTER_ID.with(|id| *id.borrow()) {
+        return Err(Error::Unauthorized);
+    }
+    Ok(
+        BLOCK_LISTS.with(|b| b.borrow().get(&StorablePrincipal(user)))
+            .map_or(false, |block_list| block_list.blocked.contains(&other))
+    )
+}
+
+// Used by governance to check tenure and recent activity without exposing exact last-seen times.
+// It ignores the user's privacy settings, so only the governance canister may call it.
+#[query]
+fn get_activity_signal(id: Principal) -> Result<Option<ActivitySignal>, Error> {
+    if Some(caller()) != GOVERNANCE_CANISTER_ID.with(|g_id| *g_id.borrow()) {
+        return Err(Error::Unauthorized);
+    }
+    Ok(
+        PROFILES.with(|p| p.borrow().get(&StorablePrincipal(id))).map(|profile| ActivitySignal {
+            created_at: profile.created_at,
+            last_active_day: profile.last_seen_timestamp - (profile.last_seen_timestamp % DAY_NS),
+        })
+    )
+}
+
+// Public keys of all of a user's active devices. Sector moderators wrap the sector key once per
+// device so that every device a member uses can decrypt E2EE content.
+#[query]
+fn get_device_keys(id: Principal) -> Vec<DeviceKey> {
+    PROFILES.with(|p| p.borrow().get(&StorablePrincipal(id)))
+        .map(|profile| active_keys(&get_or_seed_key_ring(&profile)))
+        .unwrap_or_default()
+}
+
+// Batch form of `get_device_keys`, used when rotating a sector key for every member at once.
+#[query]
+fn get_device_keys_batch(ids: Vec<Principal>) -> Vec<(Principal, Vec<DeviceKey>)> {
+    ids.into_iter()
+        .map(|id| (id, get_device_keys(id)))
+        .collect()
+}
+
 #[query]
 fn get_admins() -> Vec<Principal> {
     ADMINS.with(|a| a.borrow().iter().cloned().collect())
 }
 
+#[query]
+fn get_tag_history(target_user: Principal) -> Result<Vec<TagChange>, Error> {
+    is_admin()?;
+    Ok(
+        TAG_HISTORY.with(|h| {
+            h.borrow()
+                .values()
+                .filter(|change| change.target == target_user)
+                .collect()
+        })
+    )
+}
+
 // ==================================================================================================
 // === Admin Functions ===
 // ==================================================================================================
 
+// Takes over the admin and global poster lists the global feed kept before roles moved here.
+// Returns the grants that could not be applied because the user has no profile yet.
+#[update]
+fn import_legacy_grants(
+    grants: Vec<(Principal, UserTag)>
+) -> Result<Vec<(Principal, UserTag)>, Error> {
+    if Some(caller()) != GLOBAL_FEED_CANISTER_ID.with(|id| *id.borrow()) {
+        return Err(Error::Unauthorized);
+    }
+    Ok(
+        grants
+            .into_iter()
+            .filter(|(user, tag)| matches!(grant_tag(*user, tag.clone()), Err(Error::NotFound)))
+            .collect()
+    )
+}
+
 #[update]
 fn add_admin(principal: Principal) -> Result<(), Error> {
     is_admin()?;
+    grant_tag(principal, UserTag::Admin)
+}
 
-    let storable_principal = StorablePrincipal(principal);
-    PROFILES.with(|p| {
-        let mut profiles_map = p.borrow_mut();
-        // **FIXED**: Must clone to modify
-        let mut profile = profiles_map.get(&storable_principal).ok_or(Error::NotFound)?.clone();
-
-        ADMINS.with(|a| a.borrow_mut().insert(principal));
-        profile.tags.insert(UserTag::Admin);
-        profiles_map.insert(storable_principal, profile);
+#[update]
+fn remove_admin(principal: Principal) -> Result<(), Error> {
+    is_admin()?;
+    revoke_admin(principal)
+}
 
-        Ok(())
-    })
+#[update]
+fn add_user_tag(target_user: Principal, tag: UserTag) -> Result<(), Error> {
+    is_admin()?;
+    grant_tag(target_user, tag)
 }
 
 #[update]
-fn set_user_tag(target_user: Principal, new_tag: UserTag) -> Result<(), Error> {
+fn remove_user_tag(target_user: Principal, tag: UserTag) -> Result<(), Error> {
     is_admin()?;
 
+    if tag == UserTag::Admin {
+        return revoke_admin(target_user);
+    }
+
     let storable_principal = StorablePrincipal(target_user);
     PROFILES.with(|p| {
         let mut profiles_map = p.borrow_mut();
-        // **FIXED**: Must clone to modify
         let mut profile = profiles_map.get(&storable_principal).ok_or(Error::NotFound)?.clone();
 
-        // This example replaces all other tags. To add, just use .insert()
-        profile.tags.clear();
-        profile.tags.insert(new_tag);
+        if !profile.tags.remove(&tag) {
+            return Err(Error::NotFound);
+        }
         profiles_map.insert(storable_principal, profile);
-
         Ok(())
-    })
+    })?;
+
+    record_tag_change(target_user, tag, TagAction::Removed);
+    Ok(())
 }
 
 // Export the interface for the smart contract.
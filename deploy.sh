@@ -21,6 +21,8 @@ dfx generate invite_canister
 dfx generate sector_canister
 dfx generate global_feed_canister
 dfx generate governance_canister
+dfx generate direct_message_canister
+dfx generate notification_canister
 
 echo "✅ Type declarations & Wasm Modules generated successfully."
 
@@ -53,6 +55,8 @@ echo "  > Factory Canister ID: $FACTORY_ID"
 dfx deploy governance_canister --argument "(principal \"$MY_PRINCIPAL\", principal \"$USER_ID\", principal \"$GLOBAL_FEED_ID\")"
 dfx deploy sector_registry_canister --argument "(principal \"$MY_PRINCIPAL\", principal \"$FACTORY_ID\")"
 dfx deploy invite_canister --argument "(principal \"$MY_PRINCIPAL\", principal \"$FACTORY_ID\")"
+dfx deploy direct_message_canister --argument "(principal \"$MY_PRINCIPAL\", principal \"$USER_ID\")"
+dfx deploy notification_canister --argument "(principal \"$MY_PRINCIPAL\", principal \"$USER_ID\", principal \"$GLOBAL_FEED_ID\", principal \"$FACTORY_ID\")"
 
 echo "✅ Dependent canisters deployed."
 
@@ -63,15 +67,23 @@ echo -e "\n\033[1;33mStep 3: Configuring canisters and setting dependencies...\0
 export REGISTRY_ID=$(dfx canister id sector_registry_canister)
 export INVITE_ID=$(dfx canister id invite_canister)
 export GOVERNANCE_ID=$(dfx canister id governance_canister)
+export NOTIFICATION_ID=$(dfx canister id notification_canister)
 
 echo "  > Registry Canister ID: $REGISTRY_ID"
 echo "  > Invite Canister ID: $INVITE_ID"
 echo "  > Governance Canister ID: $GOVERNANCE_ID"
+echo "  > Notification Canister ID: $NOTIFICATION_ID"
 # Tell the factory where the registry and invite canisters are
 echo "  > Linking factory to registry and invite canisters..."
 dfx canister call sector_factory_canister set_registry_canister "(principal \"$REGISTRY_ID\")"
 dfx canister call sector_factory_canister set_invite_canister "(principal \"$INVITE_ID\")"
 dfx canister call sector_factory_canister set_global_feed_canister "(principal \"$GLOBAL_FEED_ID\")"
+dfx canister call sector_factory_canister set_user_canister "(principal \"$USER_ID\")"
+
+# Let the user canister verify sector canisters and reach the global feed
+echo "  > Linking user canister to factory and global feed..."
+dfx canister call user_canister set_factory_canister "(principal \"$FACTORY_ID\")"
+dfx canister call user_canister set_global_feed_canister "(principal \"$GLOBAL_FEED_ID\")"
 
 echo " > Adding Cycles to registry"
 dfx ledger fabricate-cycles --t 100 --canister $REGISTRY_ID
@@ -81,6 +93,20 @@ echo "✅ Added Cycles complete."
 # Tell the global feed canister where the governance canister is
 echo "  > Linking global feed to governance canister..."
 dfx canister call global_feed_canister set_governance_canister "(principal \"$GOVERNANCE_ID\")"
+echo "  > Letting governance read user activity signals..."
+dfx canister call user_canister set_governance_canister "(principal \"$GOVERNANCE_ID\")"
+echo "  > Letting the direct message canister check block lists..."
+dfx canister call user_canister set_direct_message_canister "(principal \"$(dfx canister id direct_message_canister)\")"
+echo "  > Linking global feed to user canister..."
+dfx canister call global_feed_canister set_user_canister "(principal \"$USER_ID\")"
+
+# Let every event source reach the notification canister, and let it recognise governance
+echo "  > Linking event sources to the notification canister..."
+dfx canister call sector_factory_canister set_notification_canister "(principal \"$NOTIFICATION_ID\")"
+dfx canister call global_feed_canister set_notification_canister "(principal \"$NOTIFICATION_ID\")"
+dfx canister call governance_canister set_notification_canister "(principal \"$NOTIFICATION_ID\")"
+dfx canister call user_canister set_notification_canister "(principal \"$NOTIFICATION_ID\")"
+dfx canister call notification_canister set_governance_canister "(principal \"$GOVERNANCE_ID\")"
 
 echo "✅ Canister wiring complete."
 
@@ -99,4 +125,5 @@ echo "You can now access the frontend at:"
 dfx canister id frontend | xargs -I {} echo "http://127.0.0.1:4943/?canisterId={}"
 echo "--------------------------------------------------------------------"
 
-dfx canister call global_feed_canister add_global_poster "(principal \"pgwnk-mbfq6-gge4j-qyhgj-6b753-d6fsy-vzbgu-ir7x2-xprlw-z3e7u-bqe\")"
+echo "To let the development user post to the Global Feed, create its profile in the UI and then run:"
+echo "  dfx canister call user_canister add_user_tag '(principal \"pgwnk-mbfq6-gge4j-qyhgj-6b753-d6fsy-vzbgu-ir7x2-xprlw-z3e7u-bqe\", variant { GlobalPoster })'"
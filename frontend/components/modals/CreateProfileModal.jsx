@@ -52,7 +52,7 @@ const CreateProfileModal = ({ isVisible }) => {
     <dialog ref={dialogRef} className="modal bg-slate-900/50 backdrop-blur-sm">
       <div className="modal-box bg-slate-800/20 border border-glassterm-border font-mono">
         <h3 className="font-bold text-2xl text-slate-100">Finalize Registration</h3>
-        <p className="py-4 text-slate-300">Choose a unique username. This will also generate your permanent cryptographic identity. This cannot be changed later.</p>
+        <p className="py-4 text-slate-300">Choose a unique username (3-20 letters, digits or underscores). This will also generate your permanent cryptographic identity. Your username can be changed once every 30 days.</p>
         
         <form onSubmit={handleSubmit} method="dialog" className="flex flex-col space-y-4">
           <div className="form-control">
@@ -64,6 +64,8 @@ const CreateProfileModal = ({ isVisible }) => {
               onChange={(e) => setUsername(e.target.value)}
               required
               minLength={3}
+              maxLength={20}
+              pattern="[A-Za-z0-9_]+"
             />
             {error && (
               <label className="label">
@@ -9,6 +9,8 @@ import { idlFactory as inviteCanisterIdl } from 'declarations/invite_canister';
 import { idlFactory as sectorCanisterIdl } from 'declarations/sector_canister';
 import { idlFactory as globalFeedCanisterIdl } from 'declarations/global_feed_canister';
 import { idlFactory as governanceCanisterIdl } from 'declarations/governance_canister';
+import { idlFactory as directMessageCanisterIdl } from 'declarations/direct_message_canister';
+import { idlFactory as notificationCanisterIdl } from 'declarations/notification_canister';
 
 
 // Map of canister names to their CANISTER_ID from the .env file
@@ -20,6 +22,8 @@ const canisterIds = {
   sector_canister: process.env.CANISTER_ID_SECTOR_CANISTER, // Note: This will be dynamically handled for multiple sectors
   global_feed_canister: process.env.CANISTER_ID_GLOBAL_FEED_CANISTER,
   governance_canister: process.env.CANISTER_ID_GOVERNANCE_CANISTER,
+  direct_message_canister: process.env.CANISTER_ID_DIRECT_MESSAGE_CANISTER,
+  notification_canister: process.env.CANISTER_ID_NOTIFICATION_CANISTER,
 };
 
 // Map of canister names to their imported IDL factory
@@ -31,6 +35,8 @@ const idlFactories = {
   sector_canister: sectorCanisterIdl,
   global_feed_canister: globalFeedCanisterIdl,
   governance_canister: governanceCanisterIdl,
+  direct_message_canister: directMessageCanisterIdl,
+  notification_canister: notificationCanisterIdl,
 };
 
 let host = process.env.DFX_NETWORK === "ic" ? "https://icp-api.io" : "http://localhost:4943";
@@ -13,6 +13,12 @@ const useAuthStore = create((set, get) => ({
   userProfile: null,
   userCanister: null,
   status: 'initializing', // intializing / authenticated / unauthenticated
+  showHiddenContent: false, // When true, feeds include posts by blocked and muted users
+
+  setShowHiddenContent: (show) => set({ showHiddenContent: show }),
+
+  // Candid `opt bool` for the feed and chat queries' `show_hidden` argument.
+  showHiddenArg: () => (get().showHiddenContent ? [true] : []),
 
   initialize: async () => {
     try {
@@ -19,6 +19,8 @@ const useChatStore = create((set, get) => ({
   
   hasOlderMessages: true, // Becomes false when a fetch returns fewer than a full page
   pollingIntervalId: null,// To hold the ID of our `setInterval` for cleanup
+  activeThread: null,     // { root, replies } for the open thread panel, if any
+  pins: [],               // Pinned messages in the active channel
 
   // ACTIONS
 
@@ -45,10 +47,11 @@ const useChatStore = create((set, get) => ({
       
       try {
         const actor = createActor('sector_canister', { canisterId: activeSectorId, agentOptions: { identity: useAuthStore.getState().identity }});
-        const results = await actor.get_messages(activeChannel, MESSAGES_PER_PAGE, []); // `[]` for `before_id` means get latest
+        const results = await actor.get_messages(activeChannel, MESSAGES_PER_PAGE, [], useAuthStore.getState().showHiddenArg()); // `[]` for `before_id` means get latest
         
         // Results are newest first, so we reverse them for display
         set({ messages: results.reverse(), isLoading: false });
+        get().markLatestRead();
 
       } catch (err) {
         console.error("Error fetching initial messages:", err);
@@ -65,7 +68,7 @@ const useChatStore = create((set, get) => ({
     try {
       const oldestMessageId = messages[0].id;
       const actor = createActor('sector_canister', { canisterId: activeSectorId, agentOptions: { identity: useAuthStore.getState().identity }});
-      const results = await actor.get_messages(activeChannel, MESSAGES_PER_PAGE, [oldestMessageId]);
+      const results = await actor.get_messages(activeChannel, MESSAGES_PER_PAGE, [oldestMessageId], useAuthStore.getState().showHiddenArg());
 
       if (results.length < MESSAGES_PER_PAGE) {
         set({ hasOlderMessages: false });
@@ -92,12 +95,13 @@ const useChatStore = create((set, get) => ({
         try {
             const actor = createActor('sector_canister', { canisterId: activeSectorId, agentOptions: { identity: useAuthStore.getState().identity }});
             // Call the new backend function with the correct arguments
-            const result = await actor.get_new_messages(activeChannel, latestMessageId);
+            const result = await actor.get_new_messages(activeChannel, latestMessageId, useAuthStore.getState().showHiddenArg());
 
             if (result && 'Ok' in result && result.Ok.length > 0) {
                 // The result is already a vector of messages
                 const newMessages = result.Ok;
                 set(state => ({ messages: [...state.messages, ...newMessages]}));
+                get().markLatestRead();
             }
         } catch(err) {
             // It's better to log this as a warning, as polling can fail intermittently
@@ -108,6 +112,95 @@ const useChatStore = create((set, get) => ({
       set({ pollingIntervalId: intervalId });
   },
 
+  // Loads the thread a message belongs to: { root, replies } with replies oldest first.
+  fetchThread: async (messageId) => {
+    const { activeSectorId } = get();
+    if (!activeSectorId) return;
+
+    try {
+      const actor = createActor('sector_canister', { canisterId: activeSectorId, agentOptions: { identity: useAuthStore.getState().identity }});
+      const result = await actor.get_thread(messageId, [], useAuthStore.getState().showHiddenArg());
+      if ('Ok' in result) {
+        set({ activeThread: result.Ok });
+      }
+    } catch (err) {
+      console.error("Error fetching thread:", err);
+    }
+  },
+
+  closeThread: () => set({ activeThread: null }),
+
+  // Pinned messages for the active channel (moderators pin/unpin)
+  fetchPins: async () => {
+    const { activeSectorId, activeChannel } = get();
+    if (!activeSectorId || !activeChannel) return;
+
+    try {
+      const actor = createActor('sector_canister', { canisterId: activeSectorId, agentOptions: { identity: useAuthStore.getState().identity }});
+      const result = await actor.get_pins(activeChannel);
+      if ('Ok' in result) {
+        set({ pins: result.Ok });
+      }
+    } catch (err) {
+      console.error("Error fetching pins:", err);
+    }
+  },
+
+  setPinned: async (messageId, pinned) => {
+    const { activeSectorId, activeChannel } = get();
+    if (!activeSectorId || !activeChannel) return { Err: "No active channel." };
+
+    try {
+      const actor = createActor('sector_canister', { canisterId: activeSectorId, agentOptions: { identity: useAuthStore.getState().identity }});
+      const result = pinned
+        ? await actor.pin_message(activeChannel, messageId)
+        : await actor.unpin_message(activeChannel, messageId);
+      if ('Err' in result) {
+        throw new Error(Object.values(result.Err)[0]);
+      }
+      await get().fetchPins();
+      return { Ok: null };
+    } catch (err) {
+      console.error("Error updating pin:", err);
+      return { Err: err.message };
+    }
+  },
+
+  // Adds or removes a reaction. `target` is { Post: postId } or { Message: { channel_name, message_id } }.
+  // In E2EE sectors `reaction` should already be encrypted with the current sector key.
+  toggleReaction: async (target, reaction, reactedByMe) => {
+    const { activeSectorId } = get();
+    if (!activeSectorId) return { Err: "No active sector." };
+
+    try {
+      const actor = createActor('sector_canister', { canisterId: activeSectorId, agentOptions: { identity: useAuthStore.getState().identity }});
+      const bytes = typeof reaction === 'string' ? new TextEncoder().encode(reaction) : reaction;
+      const result = reactedByMe
+        ? await actor.remove_reaction(target, bytes)
+        : await actor.add_reaction(target, bytes);
+      if ('Err' in result) {
+        throw new Error(Object.values(result.Err)[0]);
+      }
+      return { Ok: null };
+    } catch (err) {
+      console.error("Error updating reaction:", err);
+      return { Err: err.message };
+    }
+  },
+
+  // Moves the read cursor for the active channel to the newest loaded message.
+  markLatestRead: async () => {
+    const { messages, activeSectorId, activeChannel } = get();
+    if (!activeSectorId || !activeChannel || messages.length === 0) return;
+
+    try {
+      const actor = createActor('sector_canister', { canisterId: activeSectorId, agentOptions: { identity: useAuthStore.getState().identity }});
+      await actor.mark_read(activeChannel, messages[messages.length - 1].id);
+    } catch (err) {
+      console.warn("Failed to update read cursor:", err);
+    }
+  },
+
   stopPolling: () => {
     const { pollingIntervalId } = get();
     if (pollingIntervalId) {
@@ -116,7 +209,9 @@ const useChatStore = create((set, get) => ({
     }
   },
   
-  sendMessage: async (content) => {
+  // `mentions` is a list of member principals picked in the composer.
+  // `replyTo` is the ID of the message being replied to, which places the new message in its thread.
+  sendMessage: async (content, mentions = [], replyTo = null) => {
     const { isSending, activeSectorId, activeChannel } = get();
     if (isSending || !content.trim()) return;
 
@@ -141,7 +236,10 @@ const useChatStore = create((set, get) => ({
       }
       
       const actor = createActor('sector_canister', { canisterId: activeSectorId, agentOptions: { identity: useAuthStore.getState().identity } });
-      await actor.send_message(activeChannel, new Uint8Array(encryptedContent), keyEpoch);
+      await actor.send_message(activeChannel, new Uint8Array(encryptedContent), keyEpoch, mentions, replyTo ? [replyTo] : []);
+      if (replyTo && get().activeThread) {
+        await get().fetchThread(replyTo);
+      }
 
     } catch(err) {
       console.error("Error sending message:", err);
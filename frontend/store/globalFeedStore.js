@@ -1,5 +1,6 @@
 import { create } from 'zustand';
 import { createActor } from '../services/ic';
+import useAuthStore from './authStore';
 
 const POSTS_PER_PAGE = 20n; // Using BigInt for consistency with canister calls
 
@@ -22,7 +23,7 @@ const useGlobalFeedStore = create((set, get) => ({
     set({ isLoading: true, error: null, page: 0n, hasMore: true });
     try {
       const actor = createActor('global_feed_canister');
-      const postsResult = await actor.get_global_feed(0n, POSTS_PER_PAGE);
+      const postsResult = await actor.get_global_feed(0n, POSTS_PER_PAGE, useAuthStore.getState().showHiddenArg());
       
       set({
         posts: postsResult,
@@ -47,7 +48,7 @@ const useGlobalFeedStore = create((set, get) => ({
     try {
       const actor = createActor('global_feed_canister');
       const nextPage = page;
-      const newPosts = await actor.get_global_feed(nextPage, POSTS_PER_PAGE);
+      const newPosts = await actor.get_global_feed(nextPage, POSTS_PER_PAGE, useAuthStore.getState().showHiddenArg());
       
       set({
         // Append new posts to the existing array
@@ -99,21 +100,12 @@ const useGlobalFeedStore = create((set, get) => ({
     try {
       const actor = createActor('global_feed_canister', { agentOptions: { identity } });
 
-      // The backend expects the UserTag as a variant object, e.g., { Admin: null }
-      const author_tag = userProfile.tags.find(tag => 'Admin' in tag || 'GlobalPoster' in tag);
-      if (!author_tag) {
-        throw new Error("User does not have Admin or GlobalPoster tag.");
-      }
-
       const postData = {
         content_markdown,
       };
 
-      const result = await actor.submit_direct_post(
-        postData,
-        userProfile.username,
-        author_tag
-      );
+      // The backend resolves the author's username and tag from the user canister
+      const result = await actor.submit_direct_post(postData);
 
       if ('Err' in result) {
         throw new Error(result.Err);
@@ -0,0 +1,143 @@
+import { create } from 'zustand';
+import { createActor } from '../services/ic';
+import useAuthStore from './authStore';
+
+const NOTIFICATIONS_PER_PAGE = 20n;
+
+const useNotificationStore = create((set, get) => ({
+  notifications: [],
+  unreadCount: 0,
+  preferences: null,
+  page: 0n,
+  hasMore: true,
+  isLoading: false,
+  error: null,
+
+  // Helper to get an authenticated notification canister actor
+  getActor: () => {
+    const { identity } = useAuthStore.getState();
+    if (!identity) return null;
+    return createActor('notification_canister', { agentOptions: { identity } });
+  },
+
+  // Fetches the first page of the inbox, replacing existing content
+  fetchInitialNotifications: async (unreadOnly = false) => {
+    const actor = get().getActor();
+    if (!actor || get().isLoading) return;
+
+    set({ isLoading: true, error: null, page: 0n, hasMore: true });
+    try {
+      const [notifications, unreadCount] = await Promise.all([
+        actor.get_my_notifications(0n, NOTIFICATIONS_PER_PAGE, unreadOnly),
+        actor.get_unread_notification_count(),
+      ]);
+      set({
+        notifications,
+        unreadCount: Number(unreadCount),
+        page: 1n,
+        hasMore: notifications.length === Number(NOTIFICATIONS_PER_PAGE),
+        isLoading: false,
+      });
+    } catch (err) {
+      console.error("Error fetching notifications:", err);
+      set({ error: "Failed to load notifications.", isLoading: false });
+    }
+  },
+
+  fetchMoreNotifications: async (unreadOnly = false) => {
+    const { isLoading, hasMore, page } = get();
+    const actor = get().getActor();
+    if (!actor || isLoading || !hasMore) return;
+
+    set({ isLoading: true });
+    try {
+      const more = await actor.get_my_notifications(page, NOTIFICATIONS_PER_PAGE, unreadOnly);
+      set(state => ({
+        notifications: [...state.notifications, ...more],
+        page: state.page + 1n,
+        hasMore: more.length === Number(NOTIFICATIONS_PER_PAGE),
+        isLoading: false,
+      }));
+    } catch (err) {
+      console.error("Error fetching more notifications:", err);
+      set({ isLoading: false });
+    }
+  },
+
+  // Cheap poll for the badge in the navigation
+  fetchUnreadCount: async () => {
+    const actor = get().getActor();
+    if (!actor) return;
+    try {
+      const unreadCount = await actor.get_unread_notification_count();
+      set({ unreadCount: Number(unreadCount) });
+    } catch (err) {
+      console.warn("Unread notification count failed:", err);
+    }
+  },
+
+  markRead: async (ids) => {
+    const actor = get().getActor();
+    if (!actor || ids.length === 0) return;
+
+    // Optimistically update the UI
+    const idSet = new Set(ids.map(id => id.toString()));
+    set(state => {
+      const newlyRead = state.notifications.filter(n => !n.is_read && idSet.has(n.id.toString())).length;
+      return {
+        notifications: state.notifications.map(n => idSet.has(n.id.toString()) ? { ...n, is_read: true } : n),
+        unreadCount: Math.max(0, state.unreadCount - newlyRead),
+      };
+    });
+    try {
+      await actor.mark_notifications_read(ids);
+    } catch (err) {
+      console.error("Error marking notifications read:", err);
+    }
+  },
+
+  markAllRead: async () => {
+    const actor = get().getActor();
+    if (!actor) return;
+
+    set(state => ({
+      notifications: state.notifications.map(n => ({ ...n, is_read: true })),
+      unreadCount: 0,
+    }));
+    try {
+      await actor.mark_all_notifications_read();
+    } catch (err) {
+      console.error("Error marking all notifications read:", err);
+    }
+  },
+
+  fetchPreferences: async () => {
+    const actor = get().getActor();
+    if (!actor) return;
+    try {
+      const preferences = await actor.get_notification_preferences();
+      set({ preferences });
+    } catch (err) {
+      console.error("Error fetching notification preferences:", err);
+    }
+  },
+
+  updatePreferences: async (preferences) => {
+    const actor = get().getActor();
+    if (!actor) return { Err: "Not authenticated." };
+    try {
+      const result = await actor.update_notification_preferences(preferences);
+      if ('Err' in result) {
+        const errorKey = Object.keys(result.Err)[0];
+        throw new Error(result.Err[errorKey] || errorKey);
+      }
+      set({ preferences });
+      return { Ok: null };
+    } catch (err) {
+      console.error("Error updating notification preferences:", err);
+      return { Err: err.message };
+    }
+  },
+}));
+
+export default useNotificationStore;
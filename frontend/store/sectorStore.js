@@ -4,6 +4,16 @@ import useAuthStore from './authStore';
 import { idlFactory as inviteCanisterIdl } from 'declarations/invite_canister';
 import { Principal } from '@dfinity/principal';
 const POSTS_PER_PAGE = 20; 
+// Mirrors the sector canister's PERM_* bits returned by get_my_permissions
+export const PERMISSIONS = {
+  MANAGE_CHANNELS: 1 << 0,
+  MANAGE_MEMBERS: 1 << 1,
+  APPROVE_GLOBAL_POSTS: 1 << 2,
+  ROTATE_KEYS: 1 << 3,
+  CREATE_INVITES: 1 << 4,
+  POST_TO_FEED: 1 << 5,
+  PIN_MESSAGES: 1 << 6,
+};
 const useSectorStore = create((set, get) => ({
   joinedSectors: [],      // Holds a list of {id, name, abbreviation}
   activeSectorData: null, // Holds details: { name, channels, my_role, etc. }
@@ -11,6 +21,8 @@ const useSectorStore = create((set, get) => ({
   isDetailsLoading: false,
   error: null,
   cryptoStatePoller: null, 
+  unreadCounts: [],       // Per-channel unread counts for the active sector
+  myPermissions: 0,       // PERMISSIONS bitset for the active sector
 
   // Fetches the list of sectors the user has joined from their profile
   fetchJoinedSectors: async () => {
@@ -121,12 +133,8 @@ const useSectorStore = create((set, get) => ({
             canisterId: newSectorId,
         });
 
-        // For public sectors, you just join. For private, the creator is already the owner/moderator.
-        // The backend `init` function for sector_canister already adds the owner as a moderator.
-        // But we still need to add it to the user's profile.
-        const { userCanister } = useAuthStore.getState();
-        await userCanister.add_joined_sector(newSectorId);
-        
+        // The backend `init` function for sector_canister already adds the owner as a moderator,
+        // and the factory records the new sector in the user's profile.
         await get().fetchJoinedSectors(); // Refresh the list in Pane 1
         set({ isDetailsLoading: false });
         return { Ok: newSectorId };
@@ -170,10 +178,7 @@ const useSectorStore = create((set, get) => ({
           throw new Error(`Failed to join sector: ${errorKey}`);
         }
 
-        // Add the sector to the user's profile
-        const { userCanister } = useAuthStore.getState();
-        await userCanister.add_joined_sector(sectorId);
-
+        // The sector records the membership in the user's profile itself
         await get().fetchJoinedSectors(); // Refresh the list
         set({ isDetailsLoading: false });
         return { Ok: sectorId };
@@ -192,7 +197,11 @@ fetchInitialSectorFeed: async () => {
     set({ isFeedLoading: true, error: null, feedPage: 0, hasMoreFeed: true });
     try {
       const sectorActor = createActor('sector_canister', { canisterId: activeSectorData.id, agentOptions: { identity } });
-      const postsResult = await sectorActor.get_sector_feed(0, POSTS_PER_PAGE);
+      const result = await sectorActor.get_sector_feed(0, POSTS_PER_PAGE, [], useAuthStore.getState().showHiddenArg());
+      if ('Err' in result) {
+        throw new Error(Object.values(result.Err)[0]);
+      }
+      const postsResult = result.Ok;
       
       set({
         sectorPosts: postsResult,
@@ -214,7 +223,11 @@ fetchInitialSectorFeed: async () => {
     set({ isFeedLoading: true });
     try {
       const sectorActor = createActor('sector_canister', { canisterId: activeSectorData.id, agentOptions: { identity } });
-      const newPosts = await sectorActor.get_sector_feed(feedPage, POSTS_PER_PAGE);
+      const result = await sectorActor.get_sector_feed(feedPage, POSTS_PER_PAGE, [], useAuthStore.getState().showHiddenArg());
+      if ('Err' in result) {
+        throw new Error(Object.values(result.Err)[0]);
+      }
+      const newPosts = result.Ok;
       
       set(state => ({
         sectorPosts: [...state.sectorPosts, ...newPosts],
@@ -228,6 +241,204 @@ fetchInitialSectorFeed: async () => {
     }
   },
   
+  // Per-channel unread counts for the sidebar, e.g. [{ channel_name, unread_count, last_read_at }]
+  fetchUnreadCounts: async () => {
+    const { activeSectorData } = get();
+    const { identity } = useAuthStore.getState();
+    if (!activeSectorData?.id || !identity) return;
+
+    try {
+      const sectorActor = createActor('sector_canister', { canisterId: activeSectorData.id, agentOptions: { identity } });
+      const result = await sectorActor.get_unread_counts();
+      if ('Ok' in result) {
+        set({ unreadCounts: result.Ok });
+      }
+    } catch (err) {
+      console.warn("Failed to fetch unread counts:", err);
+    }
+  },
+
+  fetchMyPermissions: async () => {
+    const { activeSectorData } = get();
+    const { identity } = useAuthStore.getState();
+    if (!activeSectorData?.id || !identity) return;
+
+    try {
+      const sectorActor = createActor('sector_canister', { canisterId: activeSectorData.id, agentOptions: { identity } });
+      const result = await sectorActor.get_my_permissions();
+      if ('Ok' in result) {
+        set({ myPermissions: result.Ok });
+      }
+    } catch (err) {
+      console.warn("Failed to fetch permissions:", err);
+    }
+  },
+
+  hasPermission: (permission) => (get().myPermissions & permission) !== 0,
+
+  // Sets (or clears, when `content` is null) the sector-wide announcement shown in the details.
+  // In E2EE sectors `content` must already be encrypted with the current sector key.
+  setAnnouncement: async (content) => {
+    const { activeSectorData } = get();
+    const { identity } = useAuthStore.getState();
+    if (!activeSectorData?.id || !identity) return { Err: "Not in a sector or not authenticated." };
+
+    try {
+      const sectorActor = createActor('sector_canister', { canisterId: activeSectorData.id, agentOptions: { identity } });
+      const result = content === null
+        ? await sectorActor.clear_announcement()
+        : await sectorActor.set_announcement(content, activeSectorData.current_key_epoch);
+      if ('Err' in result) {
+        throw new Error(Object.keys(result.Err)[0]);
+      }
+      await get().fetchSectorDetails(activeSectorData.id);
+      return { Ok: null };
+    } catch (err) {
+      console.error("Error updating announcement:", err);
+      return { Err: err.message };
+    }
+  },
+
+  // Asks to join a private sector that accepts join requests. Works without being a member.
+  requestToJoin: async (sectorId, message) => {
+    const { identity } = useAuthStore.getState();
+    if (!identity) return { Err: "Not authenticated." };
+
+    try {
+      const sectorActor = createActor('sector_canister', { canisterId: sectorId, agentOptions: { identity } });
+      const result = await sectorActor.request_to_join(message);
+      if ('Err' in result) {
+        throw new Error(Object.values(result.Err)[0]);
+      }
+      return { Ok: null };
+    } catch (err) {
+      console.error("Error requesting to join:", err);
+      return { Err: err.message };
+    }
+  },
+
+  fetchJoinRequests: async () => {
+    const { activeSectorData } = get();
+    const { identity } = useAuthStore.getState();
+    if (!activeSectorData?.id || !identity) return { Err: "Not in a sector or not authenticated." };
+
+    try {
+      const sectorActor = createActor('sector_canister', { canisterId: activeSectorData.id, agentOptions: { identity } });
+      const result = await sectorActor.get_join_requests();
+      if ('Err' in result) {
+        throw new Error(Object.values(result.Err)[0]);
+      }
+      return { Ok: result.Ok };
+    } catch (err) {
+      console.error("Error fetching join requests:", err);
+      return { Err: err.message };
+    }
+  },
+
+  // Approves with `role` (e.g. { Member: null }) or, when `approve` is false, denies with an optional reason.
+  decideJoinRequest: async (requester, approve, { role = { Member: null }, reason = null } = {}) => {
+    const { activeSectorData } = get();
+    const { identity } = useAuthStore.getState();
+    if (!activeSectorData?.id || !identity) return { Err: "Not in a sector or not authenticated." };
+
+    try {
+      const sectorActor = createActor('sector_canister', { canisterId: activeSectorData.id, agentOptions: { identity } });
+      const result = approve
+        ? await sectorActor.approve_join_request(requester, role)
+        : await sectorActor.deny_join_request(requester, reason ? [reason] : []);
+      if ('Err' in result) {
+        throw new Error(Object.values(result.Err)[0]);
+      }
+      return { Ok: null };
+    } catch (err) {
+      console.error("Error deciding join request:", err);
+      return { Err: err.message };
+    }
+  },
+
+  // A page of the member directory. `cursor` is the last principal seen; `roleFilter` is e.g. { Moderator: null }.
+  listMembers: async (cursor = null, roleFilter = null) => {
+    const { activeSectorData } = get();
+    const { identity } = useAuthStore.getState();
+    if (!activeSectorData?.id || !identity) return { Err: "Not in a sector or not authenticated." };
+
+    try {
+      const sectorActor = createActor('sector_canister', { canisterId: activeSectorData.id, agentOptions: { identity } });
+      const result = await sectorActor.list_members(
+        cursor === null ? [] : [cursor],
+        roleFilter === null ? [] : [roleFilter],
+      );
+      if ('Err' in result) {
+        throw new Error(Object.values(result.Err)[0]);
+      }
+      return { Ok: result.Ok };
+    } catch (err) {
+      console.error("Error listing members:", err);
+      return { Err: err.message };
+    }
+  },
+
+  // Moderators only. Returns a page of audit entries, newest first; pass the last entry's id to page back.
+  fetchAuditLog: async (cursor = null) => {
+    const { activeSectorData } = get();
+    const { identity } = useAuthStore.getState();
+    if (!activeSectorData?.id || !identity) return { Err: "Not in a sector or not authenticated." };
+
+    try {
+      const sectorActor = createActor('sector_canister', { canisterId: activeSectorData.id, agentOptions: { identity } });
+      const result = await sectorActor.get_audit_log(cursor === null ? [] : [cursor]);
+      if ('Err' in result) {
+        throw new Error(Object.values(result.Err)[0]);
+      }
+      return { Ok: result.Ok };
+    } catch (err) {
+      console.error("Error fetching audit log:", err);
+      return { Err: err.message };
+    }
+  },
+
+  // Ownership transfer: the owner offers (`newOwner` is a Principal) or withdraws an offer with
+  // `null`; the recipient then calls acceptOwnership.
+  transferOwnership: async (newOwner) => {
+    const { activeSectorData } = get();
+    const { identity } = useAuthStore.getState();
+    if (!activeSectorData?.id || !identity) return { Err: "Not in a sector or not authenticated." };
+
+    try {
+      const sectorActor = createActor('sector_canister', { canisterId: activeSectorData.id, agentOptions: { identity } });
+      const result = newOwner === null
+        ? await sectorActor.cancel_ownership_transfer()
+        : await sectorActor.transfer_ownership(newOwner);
+      if ('Err' in result) {
+        throw new Error(Object.values(result.Err)[0]);
+      }
+      await get().fetchSectorDetails(activeSectorData.id);
+      return { Ok: null };
+    } catch (err) {
+      console.error("Error updating ownership transfer:", err);
+      return { Err: err.message };
+    }
+  },
+
+  acceptOwnership: async () => {
+    const { activeSectorData } = get();
+    const { identity } = useAuthStore.getState();
+    if (!activeSectorData?.id || !identity) return { Err: "Not in a sector or not authenticated." };
+
+    try {
+      const sectorActor = createActor('sector_canister', { canisterId: activeSectorData.id, agentOptions: { identity } });
+      const result = await sectorActor.accept_ownership();
+      if ('Err' in result) {
+        throw new Error(Object.values(result.Err)[0]);
+      }
+      await get().fetchSectorDetails(activeSectorData.id);
+      return { Ok: null };
+    } catch (err) {
+      console.error("Error accepting ownership:", err);
+      return { Err: err.message };
+    }
+  },
+
   updateSectorConfig: async (updateData) => {
     const { activeSectorData } = get();
     const { identity } = useAuthStore.getState();
@@ -236,7 +447,13 @@ fetchInitialSectorFeed: async () => {
     set({ isDetailsLoading: true, error: null });
     try {
         const sectorActor = createActor('sector_canister', { canisterId: activeSectorData.id, agentOptions: { identity } });
-        const result = await sectorActor.update_sector_config(updateData);
+        // `memberDirectoryVisible` and `joinRequestsEnabled` are optional; leaving them out keeps the current settings.
+        const { memberDirectoryVisible, joinRequestsEnabled, ...fields } = updateData;
+        const result = await sectorActor.update_sector_config({
+          ...fields,
+          member_directory_visible: memberDirectoryVisible === undefined ? [] : [memberDirectoryVisible],
+          join_requests_enabled: joinRequestsEnabled === undefined ? [] : [joinRequestsEnabled],
+        });
 
         if('Err' in result) {
             throw new Error(Object.keys(result.Err)[0]);
@@ -284,8 +501,8 @@ fetchInitialSectorFeed: async () => {
   // function to perform key Rotation
   performKeyRotation: async () => {
     const { activeSectorData } = get();
-    const { identity } = useAuthStore.getState();
-    if (!activeSectorData || !identity) return { Err: "Not in a sector." };
+    const { identity, userCanister } = useAuthStore.getState();
+    if (!activeSectorData || !identity || !userCanister) return { Err: "Not in a sector." };
     
     set({ isDetailsLoading: true }); // Use the details loader for feedback
     
@@ -301,16 +518,17 @@ fetchInitialSectorFeed: async () => {
       const newSectorKey = await cryptoService.generateSectorKey();
       const newEpochId = activeSectorData.current_key_epoch + 1;
 
-      // For each member, get their public key and wrap the new sector key
-      const keyBatchPromises = members.map(async (memberPrincipal) => {
-        const profile = await getUserProfile(memberPrincipal);
-        if (!profile) throw new Error(`Could not find profile for member ${memberPrincipal.toText()}`);
-        
-        const publicKeyJwk = JSON.parse(new TextDecoder().decode(profile.public_key));
-        const userPublicKey = await cryptoService.importKeyJwk(publicKeyJwk, 'public');
+      // Wrap the new sector key once for every registered device of every member
+      const devicesByMember = await userCanister.get_device_keys_batch(members);
+      const keyBatchPromises = devicesByMember.flatMap(([memberPrincipal, devices]) => {
+        if (devices.length === 0) throw new Error(`No device keys registered for member ${memberPrincipal.toText()}`);
+        return devices.map(async (device) => {
+          const publicKeyJwk = JSON.parse(new TextDecoder().decode(new Uint8Array(device.public_key)));
+          const devicePublicKey = await cryptoService.importKeyJwk(publicKeyJwk, 'public');
 
-        const wrappedKey = await cryptoService.wrapSectorKey(newSectorKey, userPublicKey);
-        return [memberPrincipal, new Uint8Array(wrappedKey)];
+          const wrappedKey = await cryptoService.wrapSectorKey(newSectorKey, devicePublicKey);
+          return [memberPrincipal, device.id, new Uint8Array(wrappedKey)];
+        });
       });
       
       const key_batch = await Promise.all(keyBatchPromises);